@@ -1,12 +1,20 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use super::*;
 
-#[derive(Clone, Debug, PartialEq, Trace, Finalize)]
+#[derive(Clone, Debug, PartialEq, Trace)]
 pub struct Bindings {
 	bindings: BTreeMap<String, LCell<Value>>,
+	/// Names bound by `defconst`, which can't be rebound in this scope.
+	constants: BTreeSet<String>,
 	parent: Option<LCell<Bindings>>,
 }
 
+impl Finalize for Bindings {
+	fn finalize(&self) {
+		forget_frozen(self);
+	}
+}
+
 impl Bindings {
 	pub fn get_binding(&self, id: &Value) -> LCell<Value> {
 		if let &Value::Ident(ref i) = id {
@@ -24,6 +32,7 @@ impl Bindings {
 
 	pub fn set_binding(&mut self, id: &Value, v: LCell<Value>) {
 		if let &Value::Ident(ref i) = id {
+			self.check_not_constant(i);
 			let mut bind_map = &mut self.bindings;
 			bind_map.insert(i.clone(), v);
 		} else {
@@ -36,6 +45,7 @@ impl Bindings {
 			parent.borrow_mut().set_root_binding(id, v);
 		} else {
 			if let &Value::Ident(ref i) = id {
+				self.check_not_constant(i);
 				let mut bind_map = &mut self.bindings;
 				bind_map.insert(i.clone(), v);
 			} else {
@@ -43,25 +53,54 @@ impl Bindings {
 			}
 		}
 	}
+
+	pub fn set_constant(&mut self, id: &Value, v: LCell<Value>) {
+		self.set_binding(id, v);
+		if let &Value::Ident(ref i) = id {
+			self.constants.insert(i.clone());
+		}
+	}
+
+	/// The bindings made in this scope, leaving out those of its parents.
+	pub fn own_bindings(&self) -> BTreeMap<String, LCell<Value>> {
+		self.bindings.clone()
+	}
+
+	/// The names bound in this scope or any of its parents.
+	pub fn names(&self) -> BTreeSet<String> {
+		let mut names = match self.parent {
+			Some(ref parent) => parent.borrow().names(),
+			None => BTreeSet::new(),
+		};
+		names.extend(self.bindings.keys().cloned());
+		names
+	}
+
+	fn check_not_constant(&self, name: &str) {
+		if self.constants.contains(name) {
+			panic!("cannot rebind the constant {}", name)
+		}
+	}
 }
 
 pub fn make_root_bindings(
-	funs: Vec<(&str, HostFunc)>,
-	special: Vec<(&str, HostFunc)>,
+	funs: &'static [Builtin],
+	special: &'static [Builtin],
 	vals: Vec<(&str, LCell<Value>)>) -> Bindings
 {
 	let mut bindings = BTreeMap::new();
 	for (name, val) in vals {
 		bindings.insert(name.to_string(), val);
 	}
-	for (name, hf) in funs {
-		bindings.insert(name.to_string(), lcell(Value::Fn(Func::HFunc(hf), true)));
+	for b in funs {
+		bindings.insert(b.name.to_string(), lcell(Value::Fn(Func::HFunc(b), true)));
 	}
-	for (name, hf) in special {
-		bindings.insert(name.to_string(), lcell(Value::Fn(Func::HFunc(hf), false)));
+	for b in special {
+		bindings.insert(b.name.to_string(), lcell(Value::Fn(Func::HFunc(b), false)));
 	}
 	Bindings {
 		bindings: bindings,
+		constants: BTreeSet::new(),
 		parent: None,
 	}
 }
@@ -69,6 +108,7 @@ pub fn make_root_bindings(
 pub fn make_empty_bindings(parent: LCell<Bindings>) -> Bindings {
 	Bindings {
 		bindings: BTreeMap::new(),
+		constants: BTreeSet::new(),
 		parent: Some(parent),
 	}
 }
@@ -1,76 +1,412 @@
 use super::*;
+use std::cmp::Ordering;
 
 pub fn default_root() -> LCell<Bindings> {
-	lcell(make_root_bindings(vec![
-		("print", fn_print),
-		("eval", fn_eval),
-		("read", fn_read),
-
-		("cons", fn_cons),
-		("list", fn_list),
-		("head", fn_head),
-		("tail", fn_tail),
-		("last", fn_last),
-		("cat", fn_cat),
-		("#", fn_idx),
-
-		("list?", fn_is_list),
-		("int?", fn_is_int),
-		("bool?", fn_is_bool),
-
-		("+", fn_add),
-		("*", fn_mul),
-		("-", fn_sub),
-		("/", fn_div),
-		("mod", fn_mod),
-
-		("=", fn_eq),
-		("<", fn_lt),
-		("<=", fn_le),
-		("!=", fn_ne),
-		(">", fn_gt),
-		(">=", fn_ge),
-
-		("exit", fn_exit),
-	],
-	special_forms(),
-	vec![
+	lcell(make_root_bindings(BUILTINS, SPECIAL_FORMS, vec![
 		("EOF", eof()),
 		("true", boolean(true)),
 		("false", boolean(false)),
 	]))
 }
 
-fn fn_is_list(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+/// The interpreter checks calls against these registrations, so the
+/// functions below take the arguments they are guaranteed without checking.
+pub static BUILTINS: &'static [Builtin] = &[
+	Builtin { name: "print", fun: fn_print, min_args: 0, max_args: None, arg_types: &[],
+		args: "x...",
+		doc: "Prints each argument on its own line, strings without their quotes. Returns true." },
+	Builtin { name: "eval", fun: fn_eval, min_args: 0, max_args: None, arg_types: &[],
+		args: "expr...",
+		doc: "Evaluates each expression in the current environment and returns the value of the last." },
+	Builtin { name: "read", fun: fn_read, min_args: 0, max_args: Some(0), arg_types: &[],
+		args: "",
+		doc: "Reads the next list from standard input, or returns EOF at the end of the input." },
+
+	Builtin { name: "cons", fun: fn_cons, min_args: 2, max_args: Some(2), arg_types: &[],
+		args: "head tail",
+		doc: "Returns a new pair of head and tail." },
+	Builtin { name: "list", fun: fn_list, min_args: 0, max_args: None, arg_types: &[],
+		args: "x...",
+		doc: "Returns a new list of its arguments." },
+	Builtin { name: "head", fun: fn_head, min_args: 1, max_args: Some(1), arg_types: &["pair"],
+		args: "pair",
+		doc: "Returns the first element of pair." },
+	Builtin { name: "tail", fun: fn_tail, min_args: 1, max_args: Some(1), arg_types: &["pair"],
+		args: "pair",
+		doc: "Returns the rest of pair after its first element." },
+	Builtin { name: "last", fun: fn_last, min_args: 0, max_args: None, arg_types: &[],
+		args: "x...",
+		doc: "Returns its last argument, or nil without arguments." },
+	Builtin { name: "cat", fun: fn_cat, min_args: 0, max_args: None, arg_types: &["list"],
+		args: "list...",
+		doc: "Returns a new list of the elements of all the lists in order." },
+	Builtin { name: "#", fun: fn_idx, min_args: 2, max_args: Some(2), arg_types: &["int", "list"],
+		args: "n list",
+		doc: "Returns the nth element of list, counting from 1." },
+	Builtin { name: "deep-copy", fun: fn_deep_copy, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Returns a copy of x sharing no pairs with it, with shared structure and cycles preserved. The copy of a frozen value is mutable." },
+	Builtin { name: "set-head!", fun: fn_set_head, min_args: 2, max_args: Some(2), arg_types: &["pair", "any"],
+		args: "pair x",
+		doc: "Replaces the head of pair with x and returns pair." },
+	Builtin { name: "set-tail!", fun: fn_set_tail, min_args: 2, max_args: Some(2), arg_types: &["pair", "any"],
+		args: "pair x",
+		doc: "Replaces the tail of pair with x and returns pair." },
+	Builtin { name: "length", fun: fn_length, min_args: 1, max_args: Some(1), arg_types: &["list"],
+		args: "list",
+		doc: "Returns the number of elements in list." },
+	Builtin { name: "reverse", fun: fn_reverse, min_args: 1, max_args: Some(1), arg_types: &["list"],
+		args: "list",
+		doc: "Returns a new list of the elements of list in reverse order." },
+	Builtin { name: "member", fun: fn_member, min_args: 2, max_args: Some(2), arg_types: &["any", "list"],
+		args: "x list",
+		doc: "Returns the first tail of list whose head is equal? to x, or nil." },
+	Builtin { name: "assoc", fun: fn_assoc, min_args: 2, max_args: Some(2), arg_types: &["any", "list"],
+		args: "key alist",
+		doc: "Returns the first entry of alist whose head is equal? to key, or nil." },
+	Builtin { name: "take", fun: fn_take, min_args: 2, max_args: Some(2), arg_types: &["int", "list"],
+		args: "n list",
+		doc: "Returns a new list of the first n elements of list." },
+	Builtin { name: "drop", fun: fn_drop, min_args: 2, max_args: Some(2), arg_types: &["int", "list"],
+		args: "n list",
+		doc: "Returns list without its first n elements." },
+	Builtin { name: "zip", fun: fn_zip, min_args: 0, max_args: None, arg_types: &["list"],
+		args: "list...",
+		doc: "Returns a list of lists of the elements at the same position in each list, as long as the shortest." },
+	Builtin { name: "flatten", fun: fn_flatten, min_args: 1, max_args: Some(1), arg_types: &["list"],
+		args: "list",
+		doc: "Returns a list of the non-list elements of list and of all the lists nested in it." },
+	Builtin { name: "range", fun: fn_range, min_args: 1, max_args: Some(3), arg_types: &["int"],
+		args: "[start] end [step]",
+		doc: "Returns the list of integers from start, 0 by default, up to but not including end, by step." },
+	Builtin { name: "seq", fun: fn_seq, min_args: 1, max_args: Some(1), arg_types: &["int"],
+		args: "n",
+		doc: "Returns the list of integers from 1 to n." },
+
+	Builtin { name: "map", fun: fn_map, min_args: 2, max_args: None, arg_types: &["fn", "any"],
+		args: "f seq...",
+		doc: "Returns the list of the results of applying f to the elements at each position of the sequences, as long as the shortest." },
+	Builtin { name: "filter", fun: fn_filter, min_args: 2, max_args: Some(2), arg_types: &["fn", "list"],
+		args: "pred list",
+		doc: "Returns a list of the elements of list for which pred is true." },
+	Builtin { name: "remove", fun: fn_remove, min_args: 2, max_args: Some(2), arg_types: &["fn", "list"],
+		args: "pred list",
+		doc: "Returns a list of the elements of list for which pred is false." },
+	Builtin { name: "find", fun: fn_find, min_args: 2, max_args: Some(2), arg_types: &["fn", "list"],
+		args: "pred list",
+		doc: "Returns the first element of list for which pred is true, or nil." },
+	Builtin { name: "any?", fun: fn_any, min_args: 2, max_args: Some(2), arg_types: &["fn", "list"],
+		args: "pred list",
+		doc: "Whether pred is true for some element of list." },
+	Builtin { name: "every?", fun: fn_every, min_args: 2, max_args: Some(2), arg_types: &["fn", "list"],
+		args: "pred list",
+		doc: "Whether pred is true for every element of list." },
+	Builtin { name: "fold", fun: fn_fold, min_args: 3, max_args: Some(3), arg_types: &["fn", "any"],
+		args: "f init seq",
+		doc: "Combines init with each element of seq in turn as (f acc x) and returns the result." },
+	Builtin { name: "reduce", fun: fn_reduce, min_args: 2, max_args: Some(3), arg_types: &["fn", "any"],
+		args: "f [init] seq",
+		doc: "Like fold, starting from the first element of seq when init is left out." },
+
+	Builtin { name: "list?", fun: fn_is_list, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is nil or a pair." },
+	Builtin { name: "proper-list?", fun: fn_is_proper_list, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is a list ending in nil, and so is neither dotted nor cyclic." },
+	Builtin { name: "int?", fun: fn_is_int, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is an integer." },
+	Builtin { name: "bool?", fun: fn_is_bool, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is true or false." },
+	Builtin { name: "fn?", fun: fn_is_fn, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is a function that evaluates its arguments." },
+	Builtin { name: "special?", fun: fn_is_special, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is a special form, which receives its arguments unevaluated." },
+	Builtin { name: "symbol?", fun: fn_is_symbol, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is a symbol." },
+	Builtin { name: "string?", fun: fn_is_string, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is a string." },
+	Builtin { name: "nil?", fun: fn_is_nil, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is nil." },
+	Builtin { name: "pair?", fun: fn_is_pair, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is a pair." },
+	Builtin { name: "eof?", fun: fn_is_eof, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is the EOF returned at the end of input." },
+	Builtin { name: "type-of", fun: fn_type_of, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Returns the name of the most specific type of x as a symbol." },
+	Builtin { name: "procedure-arity", fun: fn_procedure_arity, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "f",
+		doc: "Returns the number of arguments f takes, or nil if it takes any number." },
+	Builtin { name: "host-fn?", fun: fn_is_host_fn, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "f",
+		doc: "Whether f is implemented natively rather than in L3." },
+	Builtin { name: "evaluates-args?", fun: fn_evaluates_args, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "f",
+		doc: "Whether f receives its arguments evaluated, false for special forms." },
+
+	Builtin { name: "+", fun: fn_add, min_args: 0, max_args: None, arg_types: &["int"],
+		args: "n...",
+		doc: "Returns the sum of the integers." },
+	Builtin { name: "*", fun: fn_mul, min_args: 0, max_args: None, arg_types: &["int"],
+		args: "n...",
+		doc: "Returns the product of the integers." },
+	Builtin { name: "-", fun: fn_sub, min_args: 1, max_args: None, arg_types: &["int"],
+		args: "n m...",
+		doc: "Subtracts each m from n, or negates n when it is alone." },
+	Builtin { name: "/", fun: fn_div, min_args: 2, max_args: None, arg_types: &["int"],
+		args: "n m...",
+		doc: "Divides n by each m in turn, rounding toward zero." },
+	Builtin { name: "mod", fun: fn_mod, min_args: 2, max_args: Some(2), arg_types: &["int"],
+		args: "n m",
+		doc: "Returns the remainder of dividing n by m." },
+
+	Builtin { name: "eq?", fun: fn_is_eq, min_args: 1, max_args: None, arg_types: &[],
+		args: "x y...",
+		doc: "Whether all the arguments are identical: the same pair or object, or equal constants and symbols." },
+	Builtin { name: "eqv?", fun: fn_is_eqv, min_args: 1, max_args: None, arg_types: &[],
+		args: "x y...",
+		doc: "Like eq?, but integers with the same value are also the same." },
+	Builtin { name: "equal?", fun: fn_is_equal, min_args: 1, max_args: None, arg_types: &[],
+		args: "x y...",
+		doc: "Whether all the arguments are structurally equal." },
+
+	Builtin { name: "=", fun: fn_eq, min_args: 1, max_args: None, arg_types: &[],
+		args: "x y...",
+		doc: "Whether all the arguments are equal?." },
+	Builtin { name: "<", fun: fn_lt, min_args: 1, max_args: None, arg_types: &["int"],
+		args: "n m...",
+		doc: "Whether every m is less than n." },
+	Builtin { name: "<=", fun: fn_le, min_args: 1, max_args: None, arg_types: &["int"],
+		args: "n m...",
+		doc: "Whether every m is less than or equal to n." },
+	Builtin { name: "!=", fun: fn_ne, min_args: 1, max_args: None, arg_types: &[],
+		args: "x y...",
+		doc: "Whether none of the y are equal? to x." },
+	Builtin { name: ">", fun: fn_gt, min_args: 1, max_args: None, arg_types: &["int"],
+		args: "n m...",
+		doc: "Whether every m is greater than n." },
+	Builtin { name: ">=", fun: fn_ge, min_args: 1, max_args: None, arg_types: &["int"],
+		args: "n m...",
+		doc: "Whether every m is greater than or equal to n." },
+	Builtin { name: "compare", fun: fn_compare, min_args: 2, max_args: Some(2), arg_types: &[],
+		args: "x y",
+		doc: "Returns -1, 0 or 1 as x sorts before, together with or after y in the total order of all values." },
+
+	Builtin { name: "sort", fun: fn_sort, min_args: 1, max_args: Some(1), arg_types: &["list"],
+		args: "list",
+		doc: "Returns a new list of the elements of list in the order of compare." },
+	Builtin { name: "sort-by", fun: fn_sort_by, min_args: 2, max_args: Some(2), arg_types: &["fn", "list"],
+		args: "cmp list",
+		doc: "Returns a new list of the elements of list sorted by cmp, which returns an integer like compare or whether its first argument goes first." },
+
+	Builtin { name: "new", fun: fn_new, min_args: 1, max_args: None, arg_types: &["class", "any"],
+		args: "class field-value...",
+		doc: "Returns a new object of class with its fields set in order." },
+	Builtin { name: "send", fun: fn_send, min_args: 2, max_args: None, arg_types: &["object", "symbol", "any"],
+		args: "object method arg...",
+		doc: "Calls the method named by a symbol on object with the arguments." },
+	Builtin { name: "get-field", fun: fn_get_field, min_args: 2, max_args: Some(2), arg_types: &["object", "symbol"],
+		args: "object field",
+		doc: "Returns the value of a field of object." },
+	Builtin { name: "set-field!", fun: fn_set_field, min_args: 3, max_args: Some(3), arg_types: &["object", "symbol", "any"],
+		args: "object field x",
+		doc: "Sets a field of object to x." },
+	Builtin { name: "is-a?", fun: fn_is_a, min_args: 2, max_args: Some(2), arg_types: &["any", "class"],
+		args: "object class",
+		doc: "Whether object is an instance of class or of one of its subclasses." },
+	Builtin { name: "class-of", fun: fn_class_of, min_args: 1, max_args: Some(1), arg_types: &["object"],
+		args: "object",
+		doc: "Returns the class of object." },
+	Builtin { name: "responds-to?", fun: fn_responds_to, min_args: 2, max_args: Some(2), arg_types: &["any", "symbol"],
+		args: "object method",
+		doc: "Whether the class of object has the method named by a symbol." },
+
+	Builtin { name: "force", fun: fn_force, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "promise",
+		doc: "Returns the value of promise, computing it the first time. Anything else is returned as it is." },
+	Builtin { name: "make-promise", fun: fn_make_promise, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Returns a promise already forced to x, or x itself if it is a promise." },
+	Builtin { name: "promise?", fun: fn_is_promise, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is a promise." },
+	Builtin { name: "stream-head", fun: fn_stream_head, min_args: 1, max_args: Some(1), arg_types: &["any"],
+		args: "stream",
+		doc: "Returns the first element of stream." },
+	Builtin { name: "stream-tail", fun: fn_stream_tail, min_args: 1, max_args: Some(1), arg_types: &["any"],
+		args: "stream",
+		doc: "Returns stream after its first element, forcing it." },
+	Builtin { name: "stream-take", fun: fn_stream_take, min_args: 2, max_args: Some(2), arg_types: &["int", "any"],
+		args: "n stream",
+		doc: "Returns a list of the first n elements of stream." },
+	Builtin { name: "stream-map", fun: fn_stream_map, min_args: 2, max_args: Some(2), arg_types: &["fn", "any"],
+		args: "f stream",
+		doc: "Returns the lazy stream of f applied to each element of stream." },
+	Builtin { name: "stream-filter", fun: fn_stream_filter, min_args: 2, max_args: Some(2), arg_types: &["fn", "any"],
+		args: "pred stream",
+		doc: "Returns the lazy stream of the elements of stream for which pred is true." },
+	Builtin { name: "iterate", fun: fn_iterate, min_args: 2, max_args: Some(2), arg_types: &["fn", "any"],
+		args: "f x",
+		doc: "Returns the infinite stream x, (f x), (f (f x)) and so on." },
+	Builtin { name: "naturals", fun: fn_naturals, min_args: 0, max_args: Some(1), arg_types: &["int"],
+		args: "[start]",
+		doc: "Returns the stream of integers from start, 0 by default, up to the largest integer." },
+
+	Builtin { name: "make-coroutine", fun: fn_make_coroutine, min_args: 1, max_args: Some(1), arg_types: &["fn"],
+		args: "f",
+		doc: "Returns a coroutine that runs f on its own stack when first resumed." },
+	Builtin { name: "resume", fun: fn_resume, min_args: 1, max_args: None, arg_types: &["coroutine", "any"],
+		args: "coroutine arg...",
+		doc: "Runs coroutine until it yields or returns and returns that value. The first resume passes its arguments to the function, later ones return the first to yield." },
+	Builtin { name: "yield", fun: fn_yield, min_args: 0, max_args: Some(1), arg_types: &[],
+		args: "[x]",
+		doc: "Suspends the running coroutine, making x the value of its resume, and returns the first argument of the next resume." },
+	Builtin { name: "coroutine-status", fun: fn_coroutine_status, min_args: 1, max_args: Some(1), arg_types: &["coroutine"],
+		args: "coroutine",
+		doc: "Returns suspended, running or dead." },
+	Builtin { name: "coroutine?", fun: fn_is_coroutine, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is a coroutine." },
+
+	Builtin { name: "freeze", fun: fn_freeze, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Makes x and everything reachable from it immutable and returns x." },
+	Builtin { name: "frozen?", fun: fn_is_frozen, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Whether x is frozen." },
+
+	Builtin { name: "values", fun: fn_values, min_args: 0, max_args: None, arg_types: &[],
+		args: "x...",
+		doc: "Returns its arguments as multiple values. Where one value is expected this is the first, or nil without arguments." },
+	Builtin { name: "call-with-values", fun: fn_call_with_values, min_args: 2, max_args: Some(2), arg_types: &["fn"],
+		args: "producer consumer",
+		doc: "Calls consumer with the values returned by calling producer." },
+
+	Builtin { name: "make-parameter", fun: fn_make_parameter, min_args: 1, max_args: Some(2), arg_types: &["any", "fn"],
+		args: "x [converter]",
+		doc: "Returns a parameter whose value is x. Calling the parameter returns its current value. A converter is applied to x and to every value given by parameterize." },
+
+	Builtin { name: "doc", fun: fn_doc, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "f",
+		doc: "Returns the signature and documentation of f as a string." },
+	Builtin { name: "source", fun: fn_source, min_args: 1, max_args: Some(1), arg_types: &["fn"],
+		args: "f",
+		doc: "Returns the fn form defining the closure f, as a list that evaluates to the same function." },
+	Builtin { name: "help", fun: fn_help, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "name",
+		doc: "Prints the signature and documentation of what name is bound to." },
+
+	Builtin { name: "exit", fun: fn_exit, min_args: 0, max_args: Some(1), arg_types: &["int"],
+		args: "[code]",
+		doc: "Exits the interpreter with code, 0 by default." },
+];
+
+macro_rules! make_type_predicate {
+	($func:ident, $type:expr) => (
+		fn $func(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+			let first = params.borrow().iter().next().unwrap();
+			let fref = first.borrow();
+			boolean(fref.has_type($type))
+		}
+	)
+}
+
+make_type_predicate!(fn_is_list, "list");
+make_type_predicate!(fn_is_int, "int");
+make_type_predicate!(fn_is_bool, "bool");
+make_type_predicate!(fn_is_fn, "fn");
+make_type_predicate!(fn_is_special, "special");
+make_type_predicate!(fn_is_symbol, "symbol");
+make_type_predicate!(fn_is_string, "string");
+make_type_predicate!(fn_is_nil, "nil");
+make_type_predicate!(fn_is_pair, "pair");
+make_type_predicate!(fn_is_eof, "eof");
+make_type_predicate!(fn_is_promise, "promise");
+make_type_predicate!(fn_is_coroutine, "coroutine");
+
+/// A proper list ends in `()`; improper and cyclic lists are not proper.
+fn fn_is_proper_list(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = params.borrow().iter();
-	let first = it.next().expect("list? called without parameters");
-	let fref = first.borrow();
-	match *fref {
-		Value::Cons(_) | Value::Nil => boolean(true),
-		_ => boolean(false),
+	let first = it.next().unwrap();
+	let step = |v: &LCell<Value>| -> Option<LCell<Value>> {
+		match *v.borrow() {
+			Value::Cons((_, ref t)) => Some(t.clone()),
+			_ => None,
+		}
+	};
+	let (mut slow, mut fast) = (first.clone(), first);
+	loop {
+		let next = match step(&fast) {
+			None => break,
+			Some(n) => n,
+		};
+		fast = match step(&next) {
+			None => {
+				fast = next;
+				break;
+			},
+			Some(n) => n,
+		};
+		slow = step(&slow).unwrap();
+		if same_cell(&slow, &fast) || is_eq(&slow, &fast) {
+			return boolean(false);
+		}
 	}
+	let fref = fast.borrow();
+	boolean(match *fref {
+		Value::Nil => true,
+		_ => false,
+	})
 }
 
-fn fn_is_int(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+fn fn_type_of(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = params.borrow().iter();
-	let first = it.next().expect("list? called without parameters");
+	let first = it.next().unwrap();
 	let fref = first.borrow();
-	match *fref {
-		Value::Int(_) => boolean(true),
-		_ => boolean(false),
+	ident(&fref.type_precedence()[0])
+}
+
+fn fn_param<'a>(v: &'a Value, name: &str) -> (&'a Func, bool) {
+	match *v {
+		Value::Fn(ref fun, ev) => (fun, ev),
+		ref v => panic!("{} called with something not a function {}", name, v.opaque()),
 	}
 }
 
-fn fn_is_bool(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+fn fn_procedure_arity(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = params.borrow().iter();
-	let first = it.next().expect("list? called without parameters");
+	let first = it.next().unwrap();
 	let fref = first.borrow();
-	match *fref {
-		Value::False | Value::True => boolean(true),
-		_ => boolean(false),
+	match fn_param(&*fref, "procedure-arity").0.arity() {
+		Some(n) => int(n as i32),
+		None => nil(),
 	}
 }
 
+fn fn_is_host_fn(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let first = it.next().unwrap();
+	let fref = first.borrow();
+	boolean(fn_param(&*fref, "host-fn?").0.is_host())
+}
+
+fn fn_evaluates_args(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let first = it.next().unwrap();
+	let fref = first.borrow();
+	boolean(fn_param(&*fref, "evaluates-args?").1)
+}
+
 fn fn_read(_params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	read_stdin()
 }
@@ -92,43 +428,75 @@ fn fn_last(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 
 fn fn_cons(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = params.borrow().iter();
-	let h = it.next().expect("cons called with less than 2 arguments").clone();
-	let t = it.next().expect("cons called with less than 2 arguments").clone();
+	let h = it.next().unwrap();
+	let t = it.next().unwrap();
 	cons(h, t)
 }
 
 fn fn_head(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
-	let list = params.borrow().iter().next().expect("head called without a parameter");
+	let list = params.borrow().iter().next().unwrap();
 	let rf = list.borrow();
-	rf.head().clone()
+	lcell(rf.head().borrow().clone())
 }
 
 fn fn_tail(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
-	let list = params.borrow().iter().next().expect("tail called without a parameter");
+	let list = params.borrow().iter().next().unwrap();
 	let rf = list.borrow();
-	rf.tail().clone()
+	lcell(rf.tail().borrow().clone())
 }
 
-fn fn_idx(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+fn fn_deep_copy(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let v = params.borrow().iter().next().unwrap();
+	let copy = v.borrow().deep_copy();
+	lcell(copy)
+}
+
+fn fn_set_head(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let pair = it.next().unwrap();
+	let v = it.next().unwrap();
+	let cell = match *pair.borrow() {
+		Value::Cons((ref h, _)) => h.clone(),
+		ref p => panic!("set-head! called on something not a pair {}", p.opaque()),
+	};
+	if is_frozen(&cell) {
+		panic!("set-head! called on a frozen pair {}", &*pair.borrow());
+	}
+	let new_val = v.borrow().clone();
+	*cell.borrow_mut() = new_val;
+	pair
+}
+
+fn fn_set_tail(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = params.borrow().iter();
-	let first = it.next().expect("idx called without parameters");
-	let idx = if let Value::Int(n) = *first.borrow() {
-		n
-	} else {
-		panic!("idx called with non-numeric index")
+	let pair = it.next().unwrap();
+	let v = it.next().unwrap();
+	let cell = match *pair.borrow() {
+		Value::Cons((_, ref t)) => t.clone(),
+		ref p => panic!("set-tail! called on something not a pair {}", p.opaque()),
 	};
+	if is_frozen(&cell) {
+		panic!("set-tail! called on a frozen pair {}", &*pair.borrow());
+	}
+	let new_val = v.borrow().clone();
+	*cell.borrow_mut() = new_val;
+	pair
+}
 
+fn fn_idx(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let idx = int_param(&it.next().unwrap(), "#");
 	if idx < 1 {
-		panic!("idx called with wrong index")
+		panic!("# called with an index less than 1 {}", idx)
 	}
 
-	let list = it.next().expect("idx missing list");
+	let list = it.next().unwrap();
 	let mut list_it = list.borrow().iter();
 
 	let mut count = idx;
 	let mut retval = nil();
 	while count > 0 {
-		retval = list_it.next().expect("idx called with fewer elements");
+		retval = list_it.next().unwrap_or_else(|| panic!("# called with an index past the end of the list {}", idx));
 		count -= 1;
 	}
 	retval
@@ -136,7 +504,10 @@ fn fn_idx(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 
 fn fn_print(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	for p in params.borrow().iter() {
-		println!("{}", &*p.borrow());
+		match *p.borrow() {
+			Value::Str(ref s) => write_output(s),
+			ref v => write_output(&v.to_string()),
+		}
 	}
 	boolean(true)
 }
@@ -155,12 +526,260 @@ fn fn_cat(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	lcell(builder.build())
 }
 
+fn list_iter(v: &LCell<Value>, name: &str) -> ListIterator {
+	match *v.borrow() {
+		Value::Cons(_) | Value::Nil => v.borrow().iter(),
+		ref v => panic!("{} called with something not a list {}", name, v.opaque()),
+	}
+}
+
+fn int_param(v: &LCell<Value>, name: &str) -> i32 {
+	match *v.borrow() {
+		Value::Int(i) => i,
+		ref v => panic!("{} called with something not an integer {}", name, v.opaque()),
+	}
+}
+
+fn call1(f: &LCell<Value>, x: LCell<Value>, env: &LCell<Bindings>) -> LCell<Value> {
+	apply(f.clone(), cons(x, nil()), env.clone())
+}
+
+fn call2(f: &LCell<Value>, x: LCell<Value>, y: LCell<Value>, env: &LCell<Bindings>) -> LCell<Value> {
+	apply(f.clone(), cons(x, cons(y, nil())), env.clone())
+}
+
+fn fn_length(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let list = params.borrow().iter().next().unwrap();
+	int(list_iter(&list, "length").count() as i32)
+}
+
+fn fn_reverse(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let list = params.borrow().iter().next().unwrap();
+	let mut acc = nil();
+	for e in list_iter(&list, "reverse") {
+		acc = cons(e, acc);
+	}
+	acc
+}
+
+fn fn_map(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let f = it.next().unwrap();
+	let mut lists: Vec<SeqIter> = it.map(|l| seq_iter(&l, &env)).collect();
+	let mut builder = ListBuilder::new();
+	loop {
+		let mut args = ListBuilder::new();
+		for l in lists.iter_mut() {
+			match l.next() {
+				Some(e) => args.push(e),
+				None => return lcell(builder.build()),
+			}
+		}
+		builder.push(apply(f.clone(), lcell(args.build()), env.clone()));
+	}
+}
+
+fn fn_filter(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let pred = it.next().unwrap();
+	let list = it.next().unwrap();
+	lcell(list_iter(&list, "filter").filter(|e| call1(&pred, e.clone(), &env).borrow().truthy()).collect())
+}
+
+fn fn_remove(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let pred = it.next().unwrap();
+	let list = it.next().unwrap();
+	lcell(list_iter(&list, "remove").filter(|e| !call1(&pred, e.clone(), &env).borrow().truthy()).collect())
+}
+
+fn fn_find(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let pred = it.next().unwrap();
+	let list = it.next().unwrap();
+	let found = list_iter(&list, "find").find(|e| call1(&pred, e.clone(), &env).borrow().truthy());
+	found.unwrap_or_else(nil)
+}
+
+fn fn_any(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let pred = it.next().unwrap();
+	let list = it.next().unwrap();
+	let mut list_it = list_iter(&list, "any?");
+	boolean(list_it.any(|e| call1(&pred, e, &env).borrow().truthy()))
+}
+
+fn fn_every(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let pred = it.next().unwrap();
+	let list = it.next().unwrap();
+	let mut list_it = list_iter(&list, "every?");
+	boolean(list_it.all(|e| call1(&pred, e, &env).borrow().truthy()))
+}
+
+fn fn_fold(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let op = it.next().unwrap();
+	let mut acc = it.next().unwrap();
+	let list = it.next().unwrap();
+	for e in seq_iter(&list, &env) {
+		acc = call2(&op, acc, e, &env);
+	}
+	acc
+}
+
+/// `(reduce f ls)` folds with the first element as the initial value,
+/// `(reduce f init ls)` is the same as `fold`.
+fn fn_reduce(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let args: Vec<LCell<Value>> = params.borrow().iter().collect();
+	let (op, mut acc, mut list_it) = match args.len() {
+		2 => {
+			let mut list_it = seq_iter(&args[1], &env);
+			match list_it.next() {
+				None => return nil(),
+				Some(first) => (&args[0], first, list_it),
+			}
+		},
+		3 => (&args[0], args[1].clone(), seq_iter(&args[2], &env)),
+		_ => unreachable!(),
+	};
+	while let Some(e) = list_it.next() {
+		acc = call2(op, acc, e, &env);
+	}
+	acc
+}
+
+fn fn_member(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let x = it.next().unwrap();
+	let mut pos = it.next().unwrap();
+	loop {
+		let next = match *pos.borrow() {
+			Value::Cons((ref h, ref t)) => {
+				if is_equal(h, &x) {
+					break;
+				}
+				t.clone()
+			},
+			_ => return nil(),
+		};
+		pos = next;
+	}
+	pos
+}
+
+fn fn_assoc(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let key = it.next().unwrap();
+	let alist = it.next().unwrap();
+	let found = list_iter(&alist, "assoc").find(|entry| {
+		match *entry.borrow() {
+			Value::Cons((ref k, _)) => is_equal(k, &key),
+			_ => false,
+		}
+	});
+	found.unwrap_or_else(nil)
+}
+
+fn fn_take(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let n = int_param(&it.next().unwrap(), "take");
+	let list = it.next().unwrap();
+	lcell(list_iter(&list, "take").take(n.max(0) as usize).collect())
+}
+
+fn fn_drop(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let n = int_param(&it.next().unwrap(), "drop");
+	let mut pos = it.next().unwrap();
+	for _ in 0..n {
+		let next = match *pos.borrow() {
+			Value::Cons((_, ref t)) => t.clone(),
+			Value::Nil => break,
+			ref v => panic!("drop called with something not a list {}", v.opaque()),
+		};
+		pos = next;
+	}
+	pos
+}
+
+fn fn_zip(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut lists: Vec<ListIterator> = params.borrow().iter().map(|l| list_iter(&l, "zip")).collect();
+	let mut builder = ListBuilder::new();
+	if lists.is_empty() {
+		return nil();
+	}
+	loop {
+		let mut tuple = ListBuilder::new();
+		for l in lists.iter_mut() {
+			match l.next() {
+				Some(e) => tuple.push(e),
+				None => return lcell(builder.build()),
+			}
+		}
+		builder.push(lcell(tuple.build()));
+	}
+}
+
+fn fn_flatten(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let list = params.borrow().iter().next().unwrap();
+	let mut builder = ListBuilder::new();
+	let mut stack = vec![list_iter(&list, "flatten")];
+	while let Some(mut top) = stack.pop() {
+		if let Some(e) = top.next() {
+			stack.push(top);
+			let is_list = match *e.borrow() {
+				Value::Cons(_) | Value::Nil => true,
+				_ => false,
+			};
+			if is_list {
+				stack.push(e.borrow().iter());
+			} else {
+				builder.push(e);
+			}
+		}
+	}
+	lcell(builder.build())
+}
+
+/// `(range end)`, `(range start end)` or `(range start end step)`, the end
+/// is exclusive.
+fn fn_range(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let args: Vec<i32> = int_iter(params.borrow().iter()).collect();
+	let (start, end, step) = match args.len() {
+		1 => (0, args[0], 1),
+		2 => (args[0], args[1], 1),
+		3 => (args[0], args[1], args[2]),
+		_ => unreachable!(),
+	};
+	if step == 0 {
+		panic!("range called with a zero step")
+	}
+	let mut builder = ListBuilder::new();
+	let mut next = Some(start);
+	while let Some(i) = next {
+		if !((step > 0 && i < end) || (step < 0 && i > end)) {
+			break;
+		}
+		builder.push(int(i));
+		// Past the largest or smallest integer is past end too.
+		next = i.checked_add(step);
+	}
+	lcell(builder.build())
+}
+
+/// `(seq n)` is the list from 1 to n inclusive.
+fn fn_seq(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let max = int_param(&params.borrow().iter().next().unwrap(), "seq");
+	lcell((1..=max).map(int).collect())
+}
+
 fn fn_exit(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let code = match params.borrow().iter().next() {
 		None => 0,
 		Some(v) => match *v.borrow() {
 			Value::Int(i) => i,
-			ref v => panic!("exit called with something other than an integer {}", v)
+			ref v => panic!("exit called with something other than an integer {}", v.opaque())
 		}
 	};
 	std::process::exit(code);
@@ -170,7 +789,7 @@ fn int_iter<I: Iterator<Item=LCell<Value>>>(it: I) -> impl Iterator<Item=i32> {
 	it.map(|v| {
 		match &*v.borrow() {
 			&Value::Int(i) => i,
-			v => panic!("parameters contain something not an integer {}", v),
+			v => panic!("parameters contain something not an integer {}", v.opaque()),
 		}
 	})
 }
@@ -185,53 +804,41 @@ fn fn_mul(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 
 fn fn_sub(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = int_iter(params.borrow().iter());
-	if let Some(mut acc) = it.next() {
-		int(match it.next() {
-			None => -1 * acc,
-			Some(n) => {
-				acc -= n;
-				for n in it {
-					acc -= n
-				}
-				acc
+	let mut acc = it.next().unwrap();
+	int(match it.next() {
+		None => -1 * acc,
+		Some(n) => {
+			acc -= n;
+			for n in it {
+				acc -= n
 			}
-		})
-	} else {
-		panic!("sub called without a parameter")
-	}
+			acc
+		}
+	})
 }
 
 fn fn_div(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = int_iter(params.borrow().iter());
-	if let Some(mut acc) = it.next() {
-		int(match it.next() {
-			None => panic!("div got less than 2 parameters"),
-			Some(n) => {
-				acc /= n;
-				for n in it {
-					acc /= n
-				}
-				acc
-			}
-		})
-	} else {
-		panic!("sub called without a parameter")
+	let mut acc = it.next().unwrap();
+	for n in it {
+		acc /= n
 	}
+	int(acc)
 }
 
 fn fn_mod(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = int_iter(params.borrow().iter());
-	let n = it.next().expect("mod called without parameters");
-	let m = it.next().expect("mod called with less than 2 parameters");
+	let n = it.next().unwrap();
+	let m = it.next().unwrap();
 	int(n % m)
 }
 
 macro_rules! make_comparison {
 	($func:ident, $invert:tt) => (
 		fn $func(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
-			let mut it = params.borrow().iter();
-			let v0 = it.next().expect("comparison called without parameters");
-			for v in it {
+			let ints: Vec<i32> = int_iter(params.borrow().iter()).collect();
+			let v0 = ints[0];
+			for v in ints.into_iter().skip(1) {
 				if v $invert v0 { return boolean(false); }
 			}
 			boolean(true)
@@ -239,9 +846,70 @@ macro_rules! make_comparison {
 	)
 }
 
-make_comparison!(fn_eq, !=);
 make_comparison!(fn_lt, >=);
 make_comparison!(fn_le, >);
-make_comparison!(fn_ne, ==);
 make_comparison!(fn_gt, <=);
 make_comparison!(fn_ge, <);
+
+macro_rules! make_equality {
+	($func:ident, $pred:ident, $expect:expr) => (
+		fn $func(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+			let mut it = params.borrow().iter();
+			let v0 = it.next().unwrap();
+			for v in it {
+				if $pred(&v0, &v) != $expect { return boolean(false); }
+			}
+			boolean(true)
+		}
+	)
+}
+
+make_equality!(fn_eq, is_equal, true);
+make_equality!(fn_ne, is_equal, false);
+make_equality!(fn_is_eq, is_eq, true);
+make_equality!(fn_is_eqv, is_eqv, true);
+make_equality!(fn_is_equal, is_equal, true);
+
+fn ordering_int(ord: Ordering) -> LCell<Value> {
+	int(match ord {
+		Ordering::Less => -1,
+		Ordering::Equal => 0,
+		Ordering::Greater => 1,
+	})
+}
+
+fn fn_compare(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let a = it.next().unwrap();
+	let b = it.next().unwrap();
+	ordering_int(compare(&a, &b))
+}
+
+fn fn_sort(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let list = params.borrow().iter().next().unwrap();
+	let mut elems: Vec<LCell<Value>> = list.borrow().iter().collect();
+	elems.sort_by(compare);
+	lcell(elems.into_iter().collect())
+}
+
+/// The comparator either returns an integer like `compare` does, or a
+/// boolean telling whether its first argument sorts before its second.
+fn fn_sort_by(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let cmp = it.next().unwrap();
+	let list = it.next().unwrap();
+	let call = |a: &LCell<Value>, b: &LCell<Value>| {
+		apply(cmp.clone(), cons(a.clone(), cons(b.clone(), nil())), env.clone())
+	};
+	let mut elems: Vec<LCell<Value>> = list.borrow().iter().collect();
+	elems.sort_by(|a, b| {
+		let res = call(a, b);
+		let rref = res.borrow();
+		match *rref {
+			Value::Int(i) => i.cmp(&0),
+			ref v if v.truthy() => Ordering::Less,
+			_ => if call(b, a).borrow().truthy() { Ordering::Greater } else { Ordering::Equal },
+		}
+	});
+	lcell(elems.into_iter().collect())
+}
@@ -0,0 +1,208 @@
+use super::*;
+use std::cell::Cell;
+pub use corosensei::CoroutineResult;
+use corosensei::Yielder;
+use corosensei::stack::DefaultStack;
+
+/// A coroutine is resumed with arguments and the environment of the
+/// caller. The first resume passes its function in front of the arguments.
+type Resume = (LCell<Value>, LCell<Bindings>);
+type Body = corosensei::Coroutine<Resume, LCell<Value>, LCell<Value>>;
+type LYielder = Yielder<Resume, LCell<Value>>;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+	Suspended,
+	Running,
+	Dead,
+}
+
+/// A function running on its own stack, so `yield` can suspend it from any
+/// depth of evaluation and `resume` can continue it where it left off.
+pub struct Coroutine {
+	fun: LCell<Value>,
+	status: Cell<Status>,
+	/// How deeply `eval` is nested on the coroutine's stack.
+	depth: Cell<usize>,
+	yielder: Rc<Cell<*const LYielder>>,
+	body: RefCell<Body>,
+}
+
+// Only the function is traced. The values on a started coroutine's stack
+// are rooted like those on any other stack.
+unsafe impl Trace for Coroutine {
+	unsafe fn trace(&self) {
+		self.fun.trace();
+	}
+	unsafe fn root(&self) {
+		self.fun.root();
+	}
+	unsafe fn unroot(&self) {
+		self.fun.unroot();
+	}
+	fn finalize_glue(&self) {
+		Finalize::finalize(self)
+	}
+}
+
+impl Finalize for Coroutine {}
+
+impl Drop for Coroutine {
+	fn drop(&mut self) {
+		// A suspended coroutine is dropped by the collector, which cannot
+		// run the destructors on its stack while it sweeps. Throw the stack
+		// away instead, leaking the values it still holds.
+		let mut body = self.body.borrow_mut();
+		if body.started() && !body.done() {
+			unsafe { body.force_reset() };
+		}
+	}
+}
+
+impl Coroutine {
+	pub fn status(&self) -> Status {
+		self.status.get()
+	}
+}
+
+impl fmt::Display for Coroutine {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#<coroutine {}>", status_name(self.status.get()))
+	}
+}
+
+impl fmt::Debug for Coroutine {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Coroutine({})", status_name(self.status.get()))
+	}
+}
+
+fn status_name(status: Status) -> &'static str {
+	match status {
+		Status::Suspended => "suspended",
+		Status::Running => "running",
+		Status::Dead => "dead",
+	}
+}
+
+thread_local! {
+	/// The yielders of the coroutines being run, innermost last.
+	static ACTIVE: RefCell<Vec<Rc<Cell<*const LYielder>>>> = RefCell::new(Vec::new());
+}
+
+/// Keeps a coroutine on the active stack while it runs, and marks it dead
+/// if it panics. The coroutine counts the nesting of `eval` on its own
+/// stack, so the resumer's is put aside meanwhile.
+struct Running<'a> {
+	co: &'a Coroutine,
+	resumer_depth: usize,
+}
+
+impl<'a> Running<'a> {
+	fn enter(co: &'a Coroutine) -> Running<'a> {
+		co.status.set(Status::Running);
+		ACTIVE.with(|a| a.borrow_mut().push(co.yielder.clone()));
+		Running {
+			co: co,
+			resumer_depth: swap_eval_depth(co.depth.get()),
+		}
+	}
+}
+
+impl<'a> Drop for Running<'a> {
+	fn drop(&mut self) {
+		self.co.depth.set(swap_eval_depth(self.resumer_depth));
+		ACTIVE.with(|a| a.borrow_mut().pop());
+		if std::thread::panicking() {
+			self.co.status.set(Status::Dead);
+		}
+	}
+}
+
+pub fn make_coroutine(fun: LCell<Value>) -> Gc<Coroutine> {
+	let yielder = Rc::new(Cell::new(std::ptr::null()));
+	let slot = yielder.clone();
+	let stack = DefaultStack::new(EVAL_STACK_SIZE).expect("could not allocate a coroutine stack");
+	let body = Body::with_stack(stack, move |y: &LYielder, (call, env): Resume| {
+		slot.set(y as *const LYielder);
+		let (fun, args) = match *call.borrow() {
+			Value::Cons((ref fun, ref args)) => (fun.clone(), args.clone()),
+			_ => unreachable!(),
+		};
+		apply(fun, args, env)
+	});
+	Gc::new(Coroutine {
+		fun: fun,
+		status: Cell::new(Status::Suspended),
+		depth: Cell::new(0),
+		yielder: yielder,
+		body: RefCell::new(body),
+	})
+}
+
+/// Runs a coroutine until it yields or returns. The first resume passes
+/// `args` to its function, later ones make them the value of `yield`.
+pub fn resume(co: &Coroutine, args: LCell<Value>, env: LCell<Bindings>) -> CoroutineResult<LCell<Value>, LCell<Value>> {
+	match co.status.get() {
+		Status::Running => panic!("resume called on a running coroutine"),
+		Status::Dead => panic!("resume called on a dead coroutine"),
+		Status::Suspended => {},
+	}
+	let mut body = co.body.borrow_mut();
+	let args = if body.started() { args } else { cons(co.fun.clone(), args) };
+	let result = {
+		let _running = Running::enter(co);
+		body.resume((args, env))
+	};
+	co.status.set(match result {
+		CoroutineResult::Yield(_) => Status::Suspended,
+		CoroutineResult::Return(_) => Status::Dead,
+	});
+	result
+}
+
+fn coroutine_param(v: &LCell<Value>, name: &str) -> Gc<Coroutine> {
+	match *v.borrow() {
+		Value::Coroutine(ref co) => co.clone(),
+		ref v => panic!("{} called with something not a coroutine {}", name, v.opaque()),
+	}
+}
+
+pub fn fn_make_coroutine(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let fun = params.borrow().iter().next().unwrap();
+	let is_fn = fun.borrow().has_type("fn");
+	if !is_fn {
+		panic!("make-coroutine called with something not a function {}", fun.borrow().opaque());
+	}
+	lcell(Value::Coroutine(make_coroutine(fun)))
+}
+
+/// `(resume co args...)` returns the next value `co` yields, or the value
+/// its function returns.
+pub fn fn_resume(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let (co, args) = match *params.borrow() {
+		Value::Cons((ref co, ref args)) => (coroutine_param(co, "resume"), args.clone()),
+		_ => panic!("resume called without a coroutine"),
+	};
+	match resume(&co, args, env) {
+		CoroutineResult::Yield(v) | CoroutineResult::Return(v) => v,
+	}
+}
+
+/// `(yield v)` suspends the innermost running coroutine, making v the value
+/// of the `resume` that ran it, and returns the first argument of the next
+/// `resume`.
+pub fn fn_yield(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let v = params.borrow().iter().next().unwrap_or_else(nil);
+	let yielder = ACTIVE.with(|a| a.borrow().last().map(|y| y.get()))
+		.expect("yield called outside a coroutine");
+	let (args, _) = unsafe { (*yielder).suspend(v) };
+	let first = args.borrow().iter().next().unwrap_or_else(nil);
+	first
+}
+
+pub fn fn_coroutine_status(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let v = params.borrow().iter().next().unwrap();
+	let co = coroutine_param(&v, "coroutine-status");
+	ident(status_name(co.status()))
+}
@@ -0,0 +1,113 @@
+use super::*;
+
+fn signature(name: &str, args: &[String]) -> String {
+	let mut sig = format!("({}", name);
+	for arg in args {
+		sig.push(' ');
+		sig.push_str(arg);
+	}
+	sig.push(')');
+	sig
+}
+
+fn record_doc(op: &RecordOp) -> (String, String) {
+	use record::RecordOpKind::*;
+	let rtype = &op.rtype;
+	let name = op.to_string();
+	match op.kind {
+		Constructor => (signature(&name, &rtype.fields),
+			format!("Returns a new {} record.", rtype.name)),
+		Predicate => (signature(&name, &["x".to_string()]),
+			format!("Whether x is a {} record.", rtype.name)),
+		Getter(idx) => (signature(&name, &[rtype.name.clone()]),
+			format!("Returns the {} field of a {} record.", rtype.fields[idx], rtype.name)),
+		Setter(idx) => (signature(&name, &[rtype.name.clone(), rtype.fields[idx].clone()]),
+			format!("Sets the {} field of a {} record.", rtype.fields[idx], rtype.name)),
+	}
+}
+
+/// The signature of a function, what kind of function it is, and its
+/// documentation, one to a line.
+pub fn describe(fun: &Func, evaluates_args: bool) -> String {
+	let (sig, kind, doc) = match *fun {
+		Func::NFunc(ref def) => (
+			match def.name {
+				Some(ref name) => signature(name, &def.args),
+				None => format!("(fn ({}))", def.args.join(" ")),
+			},
+			"function",
+			def.doc.clone()),
+		Func::HFunc(b) => (
+			if b.args.is_empty() { format!("({})", b.name) } else { format!("({} {})", b.name, b.args) },
+			if evaluates_args { "native function" } else { "native special form" },
+			Some(b.doc.to_string())),
+		Func::Record(ref op) => {
+			let (sig, doc) = record_doc(op);
+			(sig, "native record function", Some(doc))
+		},
+		Func::Generic(ref g) => {
+			let g = g.borrow();
+			let args = match g.methods.first() {
+				Some(m) => m.fun.args.clone(),
+				None => (1..g.arity + 1).map(|i| format!("x{}", i)).collect(),
+			};
+			let methods: Vec<String> = g.methods.iter()
+				.map(|m| match m.fun.doc {
+					Some(ref doc) => format!("({}) {}", m.specializers.join(" "), doc),
+					None => format!("({})", m.specializers.join(" ")),
+				})
+				.collect();
+			let doc = if methods.is_empty() {
+				"No methods.".to_string()
+			} else {
+				format!("Methods:\n{}", methods.join("\n"))
+			};
+			(signature(&g.name, &args), "generic function", Some(doc))
+		},
+		Func::NextMethod(ref next) => (
+			"(call-next-method)".to_string(),
+			"next method",
+			Some(format!("Calls the next method of {}.", next.name))),
+		Func::Parameter(_) => (
+			"(parameter)".to_string(),
+			"native parameter",
+			Some("Returns the current value of the parameter.".to_string())),
+	};
+	let doc = doc.unwrap_or_else(|| "undocumented".to_string());
+	format!("{}\n  {}\n  {}", sig, kind, doc.replace('\n', "\n  "))
+}
+
+pub fn fn_doc(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let f = params.borrow().iter().next().expect("doc called without a parameter");
+	let fref = f.borrow();
+	match *fref {
+		Value::Fn(ref fun, ev) => lcell(Value::Str(describe(fun, ev))),
+		ref v => panic!("doc called with something not a function {}", v.opaque()),
+	}
+}
+
+/// `(source f)` returns a copy of the `fn` form defining f.
+pub fn fn_source(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let f = params.borrow().iter().next().unwrap();
+	let fref = f.borrow();
+	match *fref {
+		Value::Fn(Func::NFunc(ref def), _) => lcell(def.source().deep_copy()),
+		ref v => panic!("source called with something not a closure {}", v.opaque()),
+	}
+}
+
+/// `(help 'name)` prints the documentation of what name is bound to.
+pub fn fn_help(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let name = params.borrow().iter().next().expect("help called without a parameter");
+	let value = match *name.borrow() {
+		Value::Ident(_) => env.borrow().get_binding(&*name.borrow()),
+		_ => name.clone(),
+	};
+	let vref = value.borrow();
+	write_output(&match *vref {
+		Value::Fn(ref fun, ev) => describe(fun, ev),
+		Value::Nil => format!("{} is not bound", &*name.borrow()),
+		ref v => format!("{} is {} {}", &*name.borrow(), v.type_precedence()[0], v),
+	});
+	boolean(true)
+}
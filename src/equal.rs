@@ -0,0 +1,86 @@
+use super::*;
+use std::collections::HashSet;
+
+/// Identity on values already taken out of their cells: the constants,
+/// symbols and strings compare by contents, pairs by their head and tail
+/// cells, everything else by the identity of the object it refers to.
+fn identical_values(a: &Value, b: &Value) -> bool {
+	match (a, b) {
+		(&Value::Nil, &Value::Nil) |
+		(&Value::True, &Value::True) |
+		(&Value::False, &Value::False) |
+		(&Value::EOF, &Value::EOF) => true,
+		(&Value::Ident(ref x), &Value::Ident(ref y)) |
+		(&Value::Str(ref x), &Value::Str(ref y)) => x == y,
+		(&Value::Cons(ref x), &Value::Cons(ref y)) => pair_key(x) == pair_key(y),
+		(&Value::Fn(ref x, xev), &Value::Fn(ref y, yev)) => xev == yev && x == y,
+		(&Value::Record(ref x), &Value::Record(ref y)) => same_cell(&x.fields, &y.fields),
+		(&Value::Class(ref x), &Value::Class(ref y)) => x == y,
+		(&Value::Object(ref x), &Value::Object(ref y)) => x == y,
+		(&Value::Promise(ref x), &Value::Promise(ref y)) => same_cell(x, y),
+		(&Value::Coroutine(ref x), &Value::Coroutine(ref y)) => &**x as *const Coroutine == &**y as *const Coroutine,
+		(&Value::Module(ref x), &Value::Module(ref y)) => &**x as *const Module == &**y as *const Module,
+		_ => false,
+	}
+}
+
+/// Identity: the same cell, or values that have no identity of their own
+/// beyond their contents (the constants and symbols), or two cells holding
+/// the same pair.
+pub fn is_eq(a: &LCell<Value>, b: &LCell<Value>) -> bool {
+	same_cell(a, b) || identical_values(&*a.borrow(), &*b.borrow())
+}
+
+/// Like `is_eq`, but integers compare by value.
+pub fn is_eqv(a: &LCell<Value>, b: &LCell<Value>) -> bool {
+	match (&*a.borrow(), &*b.borrow()) {
+		(&Value::Int(x), &Value::Int(y)) => return x == y,
+		_ => {},
+	}
+	is_eq(a, b)
+}
+
+/// Structural equality, see `equal_values`.
+pub fn is_equal(a: &LCell<Value>, b: &LCell<Value>) -> bool {
+	same_cell(a, b) || equal_values(&*a.borrow(), &*b.borrow())
+}
+
+/// Structural equality. Pairs and records are compared element by element
+/// with an explicit stack, so long lists don't grow the Rust stack. A pair
+/// of pairs already under comparison is assumed equal, so cyclic structures
+/// terminate. Functions, classes and objects compare by identity.
+pub fn equal_values(a: &Value, b: &Value) -> bool {
+	let mut seen = HashSet::new();
+	let mut stack = vec![(a.clone(), b.clone())];
+	while let Some((a, b)) = stack.pop() {
+		match (&a, &b) {
+			(&Value::Int(x), &Value::Int(y)) => if x != y {
+				return false;
+			},
+			(&Value::Cons(ref x), &Value::Cons(ref y)) => {
+				let (xk, yk) = (pair_key(x), pair_key(y));
+				if xk == yk || !seen.insert((xk, yk)) {
+					continue;
+				}
+				stack.push((x.1.borrow().clone(), y.1.borrow().clone()));
+				stack.push((x.0.borrow().clone(), y.0.borrow().clone()));
+			},
+			(&Value::Record(ref x), &Value::Record(ref y)) => {
+				if !same_record_type(x, y) {
+					return false;
+				}
+				for (xf, yf) in x.field_cells().iter().zip(y.field_cells().iter()) {
+					stack.push((xf.borrow().clone(), yf.borrow().clone()));
+				}
+			},
+			(x, y) => if !identical_values(x, y) {
+				return false;
+			},
+		}
+	}
+	true
+}
+
+fn same_record_type(x: &Record, y: &Record) -> bool {
+	&*x.rtype as *const RecordType == &*y.rtype as *const RecordType
+}
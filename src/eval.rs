@@ -1,22 +1,160 @@
 use super::*;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub fn special_forms() -> Vec<(&'static str, HostFunc)> {
-	vec![
-		("quote", eval_quote),
-		("fn", eval_fn),
-		("set", eval_set),
-		("set-global", eval_set_global),
-		("if", eval_if),
-		("for", eval_for),
-		("while", eval_while),
-		("and", eval_and),
-		("or", eval_or),
-		("loop", eval_loop),
-	]
+thread_local! {
+	/// Set from another thread to stop the evaluation going on in this one.
+	static INTERRUPT: RefCell<Option<Arc<AtomicBool>>> = RefCell::new(None);
+	/// How deeply `eval` is nested on the stack it is running on.
+	static DEPTH: Cell<usize> = Cell::new(0);
 }
 
+/// The stack size of the thread the interpreter runs on and of each
+/// coroutine, since the evaluator recurses on whichever stack it runs on.
+/// The memory is only committed as it is used.
+pub const EVAL_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// How deeply `eval` may nest before giving up, which a stack of
+/// `EVAL_STACK_SIZE` holds with room to spare even in debug builds.
+const MAX_DEPTH: usize = 10000;
+
+/// One level of `eval` nesting, counted for as long as it lives.
+struct Nesting;
+
+impl Nesting {
+	/// Panics rather than let runaway recursion overflow the stack, which
+	/// would abort the process where a panic can be caught.
+	fn enter() -> Nesting {
+		let depth = DEPTH.with(|d| {
+			d.set(d.get() + 1);
+			d.get()
+		});
+		let nesting = Nesting;
+		if depth > MAX_DEPTH {
+			panic!("evaluation nested more than {} deep", MAX_DEPTH);
+		}
+		nesting
+	}
+}
+
+impl Drop for Nesting {
+	fn drop(&mut self) {
+		DEPTH.with(|d| d.set(d.get() - 1));
+	}
+}
+
+/// Makes `depth` the nesting of `eval` on this thread, returning what it
+/// was. Coroutines have stacks of their own and count from their own depth.
+pub fn swap_eval_depth(depth: usize) -> usize {
+	DEPTH.with(|d| d.replace(depth))
+}
+
+/// Lets another thread interrupt evaluation on this one by setting flag.
+pub fn set_interrupt_flag(flag: Arc<AtomicBool>) {
+	INTERRUPT.with(|i| *i.borrow_mut() = Some(flag));
+}
+
+/// Panics with "interrupted" if this thread's interrupt flag is set,
+/// clearing it.
+pub fn check_interrupt() {
+	let interrupted = INTERRUPT.with(|i| match *i.borrow() {
+		Some(ref flag) => flag.load(Ordering::Relaxed) && flag.swap(false, Ordering::Relaxed),
+		None => false,
+	});
+	if interrupted {
+		panic!("interrupted");
+	}
+}
+
+pub static SPECIAL_FORMS: &'static [Builtin] = &[
+	Builtin { name: "quote", fun: eval_quote, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "x",
+		doc: "Returns x unevaluated. The value is frozen." },
+	Builtin { name: "fn", fun: eval_fn, min_args: 1, max_args: None, arg_types: &[],
+		args: "[name] (arg...) [docstring] body...",
+		doc: "Makes a function, binding it to name if given. A string before the body is its documentation." },
+	Builtin { name: "set", fun: eval_set, min_args: 2, max_args: Some(2), arg_types: &[],
+		args: "name expr",
+		doc: "Binds name to the value of expr in the current scope and returns the value." },
+	Builtin { name: "set-global", fun: eval_set_global, min_args: 2, max_args: Some(2), arg_types: &[],
+		args: "name expr",
+		doc: "Binds name to the value of expr in the global scope and returns the value." },
+	Builtin { name: "defconst", fun: eval_defconst, min_args: 2, max_args: Some(2), arg_types: &[],
+		args: "name expr",
+		doc: "Binds name to the value of expr in the current scope for good, so it can't be rebound there." },
+	Builtin { name: "if", fun: eval_if, min_args: 2, max_args: Some(3), arg_types: &[],
+		args: "test then [else]",
+		doc: "Evaluates then if test is true, else otherwise." },
+	Builtin { name: "cond", fun: eval_cond, min_args: 0, max_args: None, arg_types: &[],
+		args: "(test body...)...",
+		doc: "Evaluates the body of the first clause whose test is true. An else test always is, and (test => f) calls f with the value of test." },
+	Builtin { name: "case", fun: eval_case, min_args: 1, max_args: None, arg_types: &[],
+		args: "key ((datum...) body...)...",
+		doc: "Evaluates the body of the first clause with a datum equal? to the value of key, or of an else clause." },
+	Builtin { name: "when", fun: eval_when, min_args: 1, max_args: None, arg_types: &[],
+		args: "test body...",
+		doc: "Evaluates body if test is true." },
+	Builtin { name: "unless", fun: eval_unless, min_args: 1, max_args: None, arg_types: &[],
+		args: "test body...",
+		doc: "Evaluates body if test is false." },
+	Builtin { name: "for", fun: eval_for, min_args: 2, max_args: None, arg_types: &[],
+		args: "binding body...",
+		doc: "Evaluates body for each element of a sequence and returns the list of the results. The binding is a name and a sequence, (index name) and a sequence, or a list of (name sequence) iterated in parallel, optionally led by an index name." },
+	Builtin { name: "while", fun: eval_while, min_args: 1, max_args: None, arg_types: &[],
+		args: "test body...",
+		doc: "Evaluates body as long as test is true and returns its last value." },
+	Builtin { name: "and", fun: eval_and, min_args: 0, max_args: None, arg_types: &[],
+		args: "expr...",
+		doc: "Evaluates the expressions until one is false and returns the last value, nil if one was false." },
+	Builtin { name: "or", fun: eval_or, min_args: 0, max_args: None, arg_types: &[],
+		args: "expr...",
+		doc: "Evaluates the expressions until one is true and returns it, or nil." },
+	Builtin { name: "loop", fun: eval_loop, min_args: 0, max_args: None, arg_types: &[],
+		args: "body...",
+		doc: "Evaluates body over and over, forever." },
+	Builtin { name: "define-record", fun: eval_define_record, min_args: 2, max_args: Some(2), arg_types: &[],
+		args: "name (field...)",
+		doc: "Defines make-name, name?, name-field accessors and set-name-field! setters for a record type." },
+	Builtin { name: "defclass", fun: eval_defclass, min_args: 3, max_args: None, arg_types: &[],
+		args: "name (superclass?) (field...) (fn method (arg...) body...)...",
+		doc: "Defines a class with fields and methods, inheriting those of the superclass. Methods get the object as self." },
+	Builtin { name: "defgeneric", fun: eval_defgeneric, min_args: 2, max_args: Some(2), arg_types: &[],
+		args: "name (arg...)",
+		doc: "Defines a generic function dispatching on the types of its arguments." },
+	Builtin { name: "defmethod", fun: eval_defmethod, min_args: 2, max_args: None, arg_types: &[],
+		args: "name (arg | (arg type)...) body...",
+		doc: "Adds a method to a generic function for arguments of the given types, defining the generic if the name is unbound. Inside it call-next-method calls the next most specific method." },
+	Builtin { name: "delay", fun: eval_delay, min_args: 1, max_args: Some(1), arg_types: &[],
+		args: "expr",
+		doc: "Returns a promise to evaluate expr when forced." },
+	Builtin { name: "lazy-seq", fun: eval_lazy_seq, min_args: 0, max_args: None, arg_types: &[],
+		args: "body...",
+		doc: "Returns a promise to evaluate body when forced." },
+	Builtin { name: "stream-cons", fun: eval_stream_cons, min_args: 2, max_args: Some(2), arg_types: &[],
+		args: "head tail",
+		doc: "Returns a stream of head followed by the stream tail evaluates to when forced." },
+	Builtin { name: "receive", fun: eval_receive, min_args: 2, max_args: None, arg_types: &[],
+		args: "formals expr body...",
+		doc: "Evaluates body with formals bound to the values of expr." },
+	Builtin { name: "let-values", fun: eval_let_values, min_args: 1, max_args: None, arg_types: &[],
+		args: "((formals expr)...) body...",
+		doc: "Evaluates body with each formals bound to the values of its expr." },
+	Builtin { name: "parameterize", fun: eval_parameterize, min_args: 1, max_args: None, arg_types: &[],
+		args: "((param expr)...) body...",
+		doc: "Evaluates body with each parameter set to the value of its expr, restoring the old values on the way out." },
+	Builtin { name: "module", fun: eval_module, min_args: 1, max_args: None, arg_types: &[],
+		args: "name (export name...) body...",
+		doc: "Evaluates body in an environment of its own with the builtins and the prelude, and binds name to a module exporting the given names." },
+	Builtin { name: "import", fun: eval_import, min_args: 1, max_args: None, arg_types: &[],
+		args: "spec [(only name...)] [(prefix p)]",
+		doc: "Binds the exports of a module and returns it. spec is a file path, looked up next to the importing file and then in L3_PATH and loaded once, or a module name. only picks the exports to bind, prefix puts p in front of their names." },
+];
+
 pub fn eval(form: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	use Value::*;
+	check_interrupt();
+	let _nesting = Nesting::enter();
 	match *form.borrow() {
 		Cons((ref h, ref t)) => {
 			let evaluated = eval(h.clone(), env.clone());
@@ -24,7 +162,12 @@ pub fn eval(form: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 			match *evref {
 				Value::Fn(ref fun, ev) => {
 					let args = if ev { eval_args(t.clone(), env.clone()) } else { t.clone() };
-					fun.eval(args, env.clone())
+					// Only the call itself may hand on the values of a `values`
+					// call, not its arguments.
+					forget_values();
+					let result = fun.eval(args, env.clone());
+					keep_values(&result);
+					result
 				},
 				ref v => {
 					panic!("{} found in function position", *v)
@@ -32,18 +175,39 @@ pub fn eval(form: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 			}
 		},
 		Value::Ident(ref i) => {
+			forget_values();
 			let envref = env.borrow();
 			let cell = envref.get_binding(&Value::Ident(i.clone()));
 			cell
 		}
-		_ => form.clone()
+		_ => {
+			forget_values();
+			form.clone()
+		}
+	}
+}
+
+pub fn eval_sequence<I: Iterator<Item=LCell<Value>>>(exprs: I, env: LCell<Bindings>) -> LCell<Value> {
+	let mut retval = nil();
+	for expr in exprs {
+		retval = eval(expr, env.clone());
+	}
+	retval
+}
+
+pub fn apply(fun: LCell<Value>, args: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let fref = fun.borrow();
+	match *fref {
+		Value::Fn(ref f, _) => f.eval(args, env),
+		ref v => panic!("{} is not a function", v.opaque()),
 	}
 }
 
 fn eval_quote(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = params.borrow().iter();
-	let first = it.next().expect("quote called without an argument");
-	first.clone()
+	let first = it.next().unwrap();
+	freeze(&first);
+	first
 }
 
 fn eval_args(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
@@ -74,6 +238,7 @@ fn eval_or(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 fn eval_loop(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	let it = arguments.borrow().iter();
 	loop {
+		check_interrupt();
 		let expr_it = it.clone();
 		for _ in expr_it.map(|expr| eval(expr, env.clone())) {}
 	}
@@ -81,7 +246,7 @@ fn eval_loop(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 
 fn eval_while(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = arguments.borrow().iter();
-	let predicate = it.next().expect("while needs a predicate");
+	let predicate = it.next().unwrap();
 	let mut retval = nil();
 	loop {
 		let evaluated_p = eval(predicate.clone(), env.clone());
@@ -96,37 +261,90 @@ fn eval_while(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	retval
 }
 
+/// `(for x seq body...)` binds each element of `seq` to `x` in turn and
+/// collects the value of the body. The binding may also be `(i x)`, which
+/// binds the index to `i` as well, or a list of `(name seq)` pairs iterated
+/// in parallel until the shortest runs out, optionally led by an index name
+/// as in `(for (i (x xs) (y ys)) body...)`.
 fn eval_for(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = arguments.borrow().iter();
-	if let Some(ref name) = it.next() {
-		if let Value::Ident(ref id) = *name.borrow() {
-			let evaluated_list = eval(it.next().expect(""), env.clone());
-			let list_it = evaluated_list.borrow().iter();
-			let mut retval = ListBuilder::new();
-			for elem in list_it {
-				env.borrow_mut().set_binding(&Value::Ident(id.clone()), elem);
-				let expr_it = it.clone();
-				let mut retval_candidate = nil();
-				for rc in expr_it.map(|expr| eval(expr, env.clone())) {
-					retval_candidate = rc;
-				}
-				retval.push(retval_candidate);
+	let binding = it.next().unwrap();
+
+	let mut index = None;
+	let mut names = Vec::new();
+	let mut seqs = Vec::new();
+	let simple = match *binding.borrow() {
+		Value::Ident(_) => true,
+		Value::Cons(_) => false,
+		_ => panic!("Identifier expected in for expr binding"),
+	};
+	if simple {
+		names.push(binding.borrow().clone());
+		seqs.push(eval(it.next().unwrap(), env.clone()));
+	} else {
+		let parts: Vec<LCell<Value>> = binding.borrow().iter().collect();
+		let all_idents = parts.iter().all(|p| p.borrow().has_type("symbol"));
+		if all_idents {
+			if parts.len() != 2 {
+				panic!("for index binding must be (index name)")
 			}
-			lcell(retval.build())
+			index = Some(parts[0].borrow().clone());
+			names.push(parts[1].borrow().clone());
+			seqs.push(eval(it.next().unwrap(), env.clone()));
 		} else {
-			panic!("Identifier expected in for expr binding")
+			for (n, part) in parts.iter().enumerate() {
+				let pref = part.borrow();
+				match *pref {
+					Value::Ident(_) if n == 0 => index = Some(pref.clone()),
+					Value::Cons(_) => {
+						let mut pair = pref.iter();
+						let name = pair.next().unwrap();
+						if !name.borrow().has_type("symbol") {
+							panic!("Identifier expected in for expr binding")
+						}
+						names.push(name.borrow().clone());
+						seqs.push(eval(pair.next().expect("for binding needs a sequence"), env.clone()));
+					},
+					ref v => panic!("for binding must be (name sequence) {}", v),
+				}
+			}
 		}
-	} else {
-		panic!("arguments expected for for expr")
 	}
-}
 
+	let mut iters: Vec<SeqIter> = seqs.iter().map(|s| seq_iter(s, &env)).collect();
+	let mut retval = ListBuilder::new();
+	let mut count = 0;
+	'outer: loop {
+		let mut elems = Vec::new();
+		for i in iters.iter_mut() {
+			match i.next() {
+				Some(elem) => elems.push(elem),
+				None => break 'outer,
+			}
+		}
+		{
+			let mut envref = env.borrow_mut();
+			if let Some(ref index) = index {
+				envref.set_binding(index, int(count));
+			}
+			for (name, elem) in names.iter().zip(elems.into_iter()) {
+				envref.set_binding(name, lcell(elem.borrow().clone()));
+			}
+		}
+		retval.push(eval_sequence(it.clone(), env.clone()));
+		count += 1;
+	}
+	lcell(retval.build())
+}
 
 fn eval_if(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = arguments.borrow().iter();
-	let predicate = it.next().expect("if needs a predicate");
-	let true_branch = it.next().expect("if needs a true branch");
+	let predicate = it.next().unwrap();
+	let true_branch = it.next().unwrap();
 	let maybe_false_branch = it.next();
+	if it.next().is_some() {
+		panic!("if called with more than 3 arguments")
+	}
 
 	let p_eval = eval(predicate, env.clone());
 	if p_eval.borrow().truthy() {
@@ -140,6 +358,91 @@ fn eval_if(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	}
 }
 
+fn eval_when(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let predicate = it.next().unwrap();
+	if eval(predicate, env.clone()).borrow().truthy() {
+		eval_sequence(it, env)
+	} else {
+		nil()
+	}
+}
+
+fn eval_unless(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let predicate = it.next().unwrap();
+	if eval(predicate, env.clone()).borrow().truthy() {
+		nil()
+	} else {
+		eval_sequence(it, env)
+	}
+}
+
+fn is_ident(v: &LCell<Value>, name: &str) -> bool {
+	match *v.borrow() {
+		Value::Ident(ref i) => i == name,
+		_ => false,
+	}
+}
+
+fn eval_cond(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	for clause in arguments.borrow().iter() {
+		let mut it = match *clause.borrow() {
+			Value::Cons(_) => clause.borrow().iter(),
+			ref v => panic!("cond clause is not a list {}", v),
+		};
+		let test = it.next().unwrap();
+		let test_value = if is_ident(&test, "else") {
+			boolean(true)
+		} else {
+			eval(test, env.clone())
+		};
+		if !test_value.borrow().truthy() {
+			continue;
+		}
+		let mut body = it.peekable();
+		let arrow = match body.peek() {
+			None => return test_value,
+			Some(first) => is_ident(first, "=>"),
+		};
+		if arrow {
+			body.next();
+			let receiver = eval(body.next().expect("cond => clause needs a receiver"), env.clone());
+			if body.next().is_some() {
+				panic!("cond => clause takes exactly one receiver")
+			}
+			return apply(receiver, cons(test_value, nil()), env);
+		}
+		return eval_sequence(body, env);
+	}
+	nil()
+}
+
+fn eval_case(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let key = eval(it.next().unwrap(), env.clone());
+	for clause in it {
+		let mut clause_it = match *clause.borrow() {
+			Value::Cons(_) => clause.borrow().iter(),
+			ref v => panic!("case clause is not a list {}", v),
+		};
+		let data = clause_it.next().unwrap();
+		let matched = if is_ident(&data, "else") {
+			true
+		} else {
+			let dref = data.borrow();
+			match *dref {
+				Value::Cons(_) | Value::Nil => dref.iter().any(|d| is_equal(&d, &key)),
+				ref v => panic!("case clause data is not a list {}", v),
+			}
+		};
+		if matched {
+			return eval_sequence(clause_it, env);
+		}
+	}
+	nil()
+}
+
 fn eval_set_global(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = arguments.borrow().iter();
 	if let Some(first) = it.next() {
@@ -172,7 +475,7 @@ fn eval_set(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 
 fn eval_fn(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 	let mut it = arguments.borrow().iter();
-	let first = it.next().expect("fn called without arguments");
+	let first = it.next().unwrap();
 	let bind = if let Value::Ident(_) = *first.borrow() {
 		Some(first.borrow())
 	} else { None };
@@ -191,9 +494,11 @@ fn eval_fn(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
 								})
 								.collect();
 
-	let listing = lcell(it.collect());
+	let (doc, listing) = split_docstring(it.collect());
 
 	let fun = lcell(Value::Fn(Func::NFunc(FunctionDef{
+		name: bind.as_ref().map(|b| b.to_string()),
+		doc: doc,
 		args: argvec,
 		listing: listing,
 		env: lcell(make_empty_bindings(env.clone())),
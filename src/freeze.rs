@@ -0,0 +1,100 @@
+use super::*;
+use std::collections::HashSet;
+
+thread_local! {
+	/// Addresses of the contents of frozen cells. A cell's entry is removed
+	/// when the collector finalizes its contents, so a later cell allocated
+	/// at the same address does not start out frozen.
+	static FROZEN: RefCell<HashSet<usize>> = RefCell::new(HashSet::new());
+}
+
+fn contents_addr<T: Trace>(cell: &LCell<T>) -> usize {
+	&*cell.borrow() as *const T as usize
+}
+
+pub fn is_frozen<T: Trace>(cell: &LCell<T>) -> bool {
+	let addr = contents_addr(cell);
+	FROZEN.with(|f| f.borrow().contains(&addr))
+}
+
+fn freeze_cell<T: Trace>(cell: &LCell<T>) -> bool {
+	let addr = contents_addr(cell);
+	FROZEN.with(|f| f.borrow_mut().insert(addr))
+}
+
+/// Called when the contents of a cell are finalized.
+pub fn forget_frozen<T>(contents: &T) {
+	let addr = contents as *const T as usize;
+	FROZEN.with(|f| {
+		if let Ok(mut frozen) = f.try_borrow_mut() {
+			if !frozen.is_empty() {
+				frozen.remove(&addr);
+			}
+		}
+	});
+}
+
+/// Freezes `v` and everything reachable from it through pairs, records and
+/// objects, so that `set-head!`, `set-tail!`, record setters and
+/// `set-field!` refuse to change any of it. Closures, classes and promises
+/// are left alone. `deep-copy` makes a mutable copy of a frozen value.
+pub fn freeze(v: &LCell<Value>) {
+	let mut stack = vec![v.clone()];
+	while let Some(cell) = stack.pop() {
+		// everything reachable from a frozen cell is frozen already, and
+		// the shared constants aren't this value's to freeze
+		if is_shared_constant(&cell) || !freeze_cell(&cell) {
+			continue;
+		}
+		let cref = cell.borrow();
+		match *cref {
+			Value::Cons((ref h, ref t)) => {
+				stack.push(t.clone());
+				stack.push(h.clone());
+			},
+			Value::Record(ref r) => {
+				freeze_cell(&r.fields);
+				stack.extend(r.field_cells());
+			},
+			Value::Object(ref o) => {
+				freeze_cell(&o.fields);
+				for name in o.class.all_fields() {
+					stack.push(o.get_field(&name));
+				}
+			},
+			_ => {},
+		}
+	}
+}
+
+pub fn fn_freeze(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let v = params.borrow().iter().next().unwrap();
+	freeze(&v);
+	v
+}
+
+pub fn fn_is_frozen(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let v = params.borrow().iter().next().unwrap();
+	let frozen = match *v.borrow() {
+		// a pair is frozen with its head, wherever the pair itself is kept
+		Value::Cons((ref h, _)) => is_frozen(h),
+		Value::Record(ref r) => is_frozen(&r.fields),
+		Value::Object(ref o) => is_frozen(&o.fields),
+		_ => is_frozen(&v),
+	};
+	boolean(frozen)
+}
+
+/// `(defconst name expr)` binds name to the value of expr in the current
+/// environment for good: `set`, `set-global` and the defining forms refuse
+/// to rebind it there.
+pub fn eval_defconst(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let name = it.next().unwrap();
+	if !name.borrow().has_type("symbol") {
+		panic!("defconst got something else than an identifier {}", &*name.borrow());
+	}
+	let value = eval(it.next().unwrap(), env.clone());
+	env.borrow_mut().set_constant(&*name.borrow(), value.clone());
+	value
+}
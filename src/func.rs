@@ -1,12 +1,69 @@
 use super::*;
-use std::cmp::Ordering;
 
 pub type HostFunc = fn(LCell<Value>, LCell<Bindings>) -> LCell<Value>;
 
+/// The registration of a function implemented in Rust. The interpreter
+/// checks the number and types of the arguments against it before calling
+/// the function, so the function can rely on them.
+pub struct Builtin {
+	pub name: &'static str,
+	pub fun: HostFunc,
+	pub min_args: usize,
+	/// `None` when the function takes any number of arguments.
+	pub max_args: Option<usize>,
+	/// The type of each argument, the last one also applying to any further
+	/// arguments. Empty when any value will do, as for special forms, which
+	/// get their arguments unevaluated.
+	pub arg_types: &'static [&'static str],
+	/// A summary of the arguments for the signature `doc` shows.
+	pub args: &'static str,
+	pub doc: &'static str,
+}
+
+impl Builtin {
+	pub fn call(&self, params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+		self.check_args(&params);
+		(self.fun)(params, env)
+	}
+
+	fn check_args(&self, params: &LCell<Value>) {
+		let mut count = 0;
+		for arg in params.borrow().iter() {
+			count += 1;
+			if self.max_args.map_or(false, |max| count > max) {
+				self.wrong_arity(count + params.borrow().iter().skip(count).count());
+			}
+			let ty = self.arg_types.get(count - 1).or(self.arg_types.last());
+			if let Some(ty) = ty {
+				if *ty != "any" && !arg.borrow().has_type(ty) {
+					panic!("{} expects argument {} to be {}, got {}", self.name, count, ty, arg.borrow().opaque());
+				}
+			}
+		}
+		if count < self.min_args {
+			self.wrong_arity(count);
+		}
+	}
+
+	fn wrong_arity(&self, count: usize) -> ! {
+		let (expected, most) = match self.max_args {
+			Some(max) if max == self.min_args => (format!("{}", max), max),
+			Some(max) => (format!("{} to {}", self.min_args, max), max),
+			None => (format!("at least {}", self.min_args), self.min_args),
+		};
+		let plural = if most == 1 { "" } else { "s" };
+		panic!("{} takes {} argument{}, got {}", self.name, expected, plural, count)
+	}
+}
+
 #[derive(Clone, Finalize, Trace)]
 pub enum Func {
 	NFunc(FunctionDef),
-	HFunc(HostFunc),
+	HFunc(&'static Builtin),
+	Record(RecordOp),
+	Generic(LCell<Generic>),
+	NextMethod(NextMethod),
+	Parameter(LCell<Parameter>),
 }
 
 impl Func {
@@ -14,7 +71,44 @@ impl Func {
 		use self::Func::*;
 		match self {
 			&NFunc(ref d) => d.eval(params, env),
-			&HFunc(fun) => fun(params, env),
+			&HFunc(b) => b.call(params, env),
+			&Record(ref op) => op.eval(params, env),
+			&Generic(ref g) => {
+				let generic = g.borrow().clone();
+				generic.eval(params, env)
+			},
+			&NextMethod(ref next) => next.eval(params, env),
+			&Parameter(ref p) => p.borrow().eval(params),
+		}
+	}
+
+	/// Number of parameters the function takes, `None` if it is variadic.
+	pub fn arity(&self) -> Option<usize> {
+		use self::Func::*;
+		use record::RecordOpKind::*;
+		match self {
+			&NFunc(ref d) => Some(d.args.len()),
+			&HFunc(b) => match b.max_args {
+				Some(max) if max == b.min_args => Some(max),
+				_ => None,
+			},
+			&Record(ref op) => match op.kind {
+				Constructor => Some(op.rtype.fields.len()),
+				Predicate | Getter(_) => Some(1),
+				Setter(_) => Some(2),
+			},
+			&Generic(ref g) => Some(g.borrow().arity),
+			&NextMethod(_) => None,
+			&Parameter(_) => Some(0),
+		}
+	}
+
+	/// Whether the function is implemented in Rust rather than in L3.
+	pub fn is_host(&self) -> bool {
+		use self::Func::*;
+		match self {
+			&HFunc(_) | &Record(_) | &Parameter(_) => true,
+			&NFunc(_) | &Generic(_) | &NextMethod(_) => false,
 		}
 	}
 }
@@ -22,8 +116,8 @@ impl Func {
 impl std::fmt::Debug for Func {
 	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), std::fmt::Error> {
 		match self {
-			&Func::HFunc(_) => write!(f, "<Host Function>"),
-			&Func::NFunc(ref def) => write!(f, "{:?}", def),
+			&Func::NFunc(ref def) => write!(f, "{}", def.opaque()),
+			fun => write!(f, "{}", fun),
 		}
 	}
 }
@@ -35,14 +129,52 @@ impl PartialEq for Func {
 				&Func::NFunc(ref odef) => def == odef,
 				_ => false,
 			},
-			_ => false,
+			&Func::Record(ref op) => match other {
+				&Func::Record(ref oop) => op == oop,
+				_ => false,
+			},
+			&Func::HFunc(fun) => match other {
+				&Func::HFunc(ofun) => std::ptr::eq(fun, ofun),
+				_ => false,
+			},
+			&Func::Generic(ref g) => match other {
+				&Func::Generic(ref og) => same_cell(g, og),
+				_ => false,
+			},
+			&Func::Parameter(ref p) => match other {
+				&Func::Parameter(ref op) => same_cell(p, op),
+				_ => false,
+			},
+			&Func::NextMethod(ref next) => match other {
+				&Func::NextMethod(ref onext) => same_cell(&next.args, &onext.args),
+				_ => false,
+			},
 		}
 	}
 }
 
-impl PartialOrd for Func {
-	fn partial_cmp(&self, _other: &Func) -> Option<Ordering> {
-		None
+fn cell_addr<T: Trace>(cell: &LCell<T>) -> usize {
+	&**cell as *const GcCell<T> as usize
+}
+
+impl Func {
+	/// What `==` compares functions by, as numbers, so that functions can be
+	/// ordered consistently with it.
+	pub fn identity(&self) -> (u8, usize, usize) {
+		use record::RecordOpKind::*;
+		match self {
+			&Func::NFunc(ref def) => (0, cell_addr(&def.listing), cell_addr(&def.env)),
+			&Func::HFunc(b) => (1, b as *const Builtin as usize, 0),
+			&Func::Record(ref op) => (2, &*op.rtype as *const RecordType as usize, match op.kind {
+				Constructor => 0,
+				Predicate => 1,
+				Getter(idx) => 2 + 2 * idx,
+				Setter(idx) => 3 + 2 * idx,
+			}),
+			&Func::Generic(ref g) => (3, cell_addr(g), 0),
+			&Func::NextMethod(ref next) => (4, cell_addr(&next.args), 0),
+			&Func::Parameter(ref p) => (5, cell_addr(p), 0),
+		}
 	}
 }
 
@@ -50,25 +182,82 @@ impl fmt::Display for Func {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
 			&Func::NFunc(ref fun) => write!(f, "{}", fun),
-			&Func::HFunc(_) => write!(f, "<Host Function>"),
+			&Func::HFunc(b) => write!(f, "#<builtin {}>", b.name),
+			&Func::Record(ref op) => write!(f, "#<record-function {}>", op),
+			&Func::Generic(ref g) => write!(f, "#<generic {}>", g.borrow().name),
+			&Func::NextMethod(ref next) => write!(f, "#<next-method {}>", next.name),
+			&Func::Parameter(_) => write!(f, "#<parameter>"),
 		}
 	}
 }
 
-#[derive(Clone, PartialEq, Trace, Finalize)]
+#[derive(Clone, Trace, Finalize)]
 pub struct FunctionDef {
+	pub name: Option<String>,
+	pub doc: Option<String>,
 	pub args: Vec<String>,
 	pub listing: LCell<Value>,
 	pub env: LCell<Bindings>,
 }
 
+/// Closures are equal when they are the same closure: the same code in the
+/// same captured environment. Neither is ever compared structurally.
+impl PartialEq for FunctionDef {
+	fn eq(&self, other: &FunctionDef) -> bool {
+		same_cell(&self.listing, &other.listing) && same_cell(&self.env, &other.env)
+	}
+}
+
 impl fmt::Debug for FunctionDef {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "FunctionDef {{ args: {:?}, listing: {:?} }}", self.args, self.listing)
+		write!(f, "FunctionDef {{ name: {:?}, args: {:?}, listing: {:?} }}", self.name, self.args, self.listing)
 	}
 }
 
+/// Splits a function body into its docstring and the expressions to
+/// evaluate. A string is only documentation when more expressions follow
+/// it, a body that is just a string returns it.
+pub fn split_docstring(mut body: Vec<LCell<Value>>) -> (Option<String>, LCell<Value>) {
+	let doc = match body.first() {
+		Some(first) if body.len() > 1 => match *first.borrow() {
+			Value::Str(ref s) => Some(s.clone()),
+			_ => None,
+		},
+		_ => None,
+	};
+	if doc.is_some() {
+		body.remove(0);
+	}
+	(doc, lcell(body.into_iter().collect()))
+}
+
 impl FunctionDef {
+	/// The `fn` form defining the function, sharing the body with it.
+	pub fn source(&self) -> Value {
+		let mut builder = ListBuilder::new();
+		builder.push(ident("fn"));
+		if let Some(ref name) = self.name {
+			builder.push(ident(name));
+		}
+		builder.push(lcell(self.args.iter().map(ident).collect()));
+		if let Some(ref doc) = self.doc {
+			builder.push(lcell(Value::Str(doc.clone())));
+		}
+		for expr in self.listing.borrow().iter() {
+			builder.push(expr);
+		}
+		builder.build()
+	}
+
+	/// A short form naming the function, for where its code would be
+	/// noise.
+	pub fn opaque(&self) -> String {
+		match self.name {
+			Some(ref name) => format!("#<closure {}>", name),
+			None => "#<closure>".to_string(),
+		}
+	}
+
 	pub fn eval(&self, params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
 		let mut func_env = make_empty_bindings(self.env.clone());
 		let mut it = params.borrow().iter();
@@ -84,19 +273,10 @@ impl FunctionDef {
 	}
 }
 
+/// Writes the `fn` form defining the function, which reads back as code
+/// making the same function.
 impl fmt::Display for FunctionDef {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "(")?;
-		let mut first = true;
-		for arg in self.args.iter() {
-			if first {
-				first = false;
-			} else {
-				write!(f, " ")?;
-			}
-			write!(f, "{}", arg)?;
-		}
-		write!(f, ")")?;
-		write!(f, "{}", &*self.listing.borrow())
+		write!(f, "{}", self.source())
 	}
 }
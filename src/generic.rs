@@ -0,0 +1,179 @@
+use super::*;
+
+#[derive(Clone, Trace, Finalize)]
+pub struct Method {
+	pub specializers: Vec<String>,
+	pub fun: FunctionDef,
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct Generic {
+	pub name: String,
+	pub arity: usize,
+	pub methods: Vec<Method>,
+}
+
+impl Generic {
+	fn add_method(&mut self, method: Method) {
+		if method.specializers.len() != self.arity {
+			panic!("method for {} takes {} arguments, the generic takes {}",
+				self.name, method.specializers.len(), self.arity)
+		}
+		self.methods.retain(|m| m.specializers != method.specializers);
+		self.methods.push(method);
+	}
+
+	/// Applicable methods for the arguments, most specific first. Methods
+	/// are ordered by comparing specializers left to right against each
+	/// argument's type precedence list.
+	fn applicable(&self, args: &[LCell<Value>]) -> Vec<Method> {
+		let precedences: Vec<Vec<String>> = args.iter()
+			.map(|a| a.borrow().type_precedence())
+			.collect();
+		let rank = |m: &Method| -> Option<Vec<usize>> {
+			m.specializers.iter().zip(precedences.iter())
+				.map(|(s, prec)| prec.iter().position(|t| t == s))
+				.collect()
+		};
+		let mut ranked: Vec<(Vec<usize>, Method)> = self.methods.iter()
+			.filter_map(|m| rank(m).map(|r| (r, m.clone())))
+			.collect();
+		ranked.sort_by(|a, b| a.0.cmp(&b.0));
+		ranked.into_iter().map(|(_, m)| m).collect()
+	}
+
+	pub fn eval(&self, params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+		let args: Vec<LCell<Value>> = params.borrow().iter().collect();
+		if args.len() != self.arity {
+			panic!("{} takes {} arguments, got {}", self.name, self.arity, args.len())
+		}
+		let methods = self.applicable(&args);
+		if methods.is_empty() {
+			let types: Vec<String> = args.iter().map(|a| a.borrow().type_precedence()[0].clone()).collect();
+			panic!("no applicable method for {} on ({})", self.name, types.join(" "))
+		}
+		NextMethod {
+			name: self.name.clone(),
+			methods: methods,
+			args: params,
+		}.eval(nil(), env)
+	}
+}
+
+/// The remaining chain of applicable methods, bound as `call-next-method`
+/// while a method body runs.
+#[derive(Clone, Trace, Finalize)]
+pub struct NextMethod {
+	pub name: String,
+	pub methods: Vec<Method>,
+	pub args: LCell<Value>,
+}
+
+impl NextMethod {
+	pub fn eval(&self, params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+		let args = if let Value::Nil = *params.borrow() {
+			self.args.clone()
+		} else {
+			params.clone()
+		};
+		let (method, rest) = match self.methods.split_first() {
+			Some(split) => split,
+			None => panic!("no next method for {}", self.name),
+		};
+		let next = NextMethod {
+			name: self.name.clone(),
+			methods: rest.to_vec(),
+			args: args.clone(),
+		};
+		let mut method_env = make_empty_bindings(method.fun.env.clone());
+		method_env.set_binding(&Value::Ident("call-next-method".to_string()),
+			lcell(Value::Fn(Func::NextMethod(next), true)));
+		FunctionDef {
+			name: method.fun.name.clone(),
+			doc: method.fun.doc.clone(),
+			args: method.fun.args.clone(),
+			listing: method.fun.listing.clone(),
+			env: lcell(method_env),
+		}.eval(args, env)
+	}
+}
+
+fn parse_generic_params(params: &LCell<Value>) -> Vec<(String, String)> {
+	params.borrow().iter().map(|p| {
+		let pref = p.borrow();
+		match *pref {
+			Value::Ident(ref i) => (i.clone(), "any".to_string()),
+			Value::Cons(_) => {
+				let mut it = pref.iter();
+				let name = it.next().unwrap();
+				let spec = it.next().expect("method parameter specializer missing");
+				let (nref, sref) = (name.borrow(), spec.borrow());
+				match (&*nref, &*sref) {
+					(&Value::Ident(ref n), &Value::Ident(ref s)) => (n.clone(), s.clone()),
+					_ => panic!("method parameter must be (name type) {}", &*pref),
+				}
+			},
+			ref v => panic!("generic parameter list containing something not an ident {}", v),
+		}
+	}).collect()
+}
+
+fn generic_name(v: &LCell<Value>, what: &str) -> Value {
+	match *v.borrow() {
+		Value::Ident(ref i) => Value::Ident(i.clone()),
+		ref v => panic!("{} name is not an identifier {}", what, v),
+	}
+}
+
+fn new_generic(name: &Value, arity: usize) -> LCell<Value> {
+	lcell(Value::Fn(Func::Generic(lcell(Generic {
+		name: name.to_string(),
+		arity: arity,
+		methods: Vec::new(),
+	})), true))
+}
+
+pub fn eval_defgeneric(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let name = generic_name(&it.next().unwrap(), "defgeneric");
+	let params = parse_generic_params(&it.next().unwrap());
+	env.borrow_mut().set_binding(&name, new_generic(&name, params.len()));
+	nil()
+}
+
+pub fn eval_defmethod(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let name = generic_name(&it.next().unwrap(), "defmethod");
+	let params = parse_generic_params(&it.next().unwrap());
+
+	let existing = env.borrow().get_binding(&name);
+	let is_generic = match *existing.borrow() {
+		Value::Fn(Func::Generic(_), _) => true,
+		Value::Nil => false,
+		ref v => panic!("defmethod {} would replace {}, which is not a generic function", name, v.opaque()),
+	};
+	let generic = if is_generic {
+		existing
+	} else {
+		let generic = new_generic(&name, params.len());
+		env.borrow_mut().set_binding(&name, generic.clone());
+		generic
+	};
+
+	let (args, specializers) = params.into_iter().unzip();
+	let (doc, listing) = split_docstring(it.collect());
+	let method = Method {
+		specializers: specializers,
+		fun: FunctionDef {
+			name: Some(name.to_string()),
+			doc: doc,
+			args: args,
+			listing: listing,
+			env: lcell(make_empty_bindings(env.clone())),
+		},
+	};
+	if let Value::Fn(Func::Generic(ref g), _) = *generic.borrow() {
+		g.borrow_mut().add_method(method);
+	}
+	nil()
+}
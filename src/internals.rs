@@ -5,8 +5,23 @@ pub fn lcell<T>(v: T) -> LCell<T>
     Gc::new(GcCell::new(v))
 }
 
+/// Identity comparison of two cells, never looks at their contents.
+pub fn same_cell<T>(a: &LCell<T>, b: &LCell<T>) -> bool
+	where T: gc::Trace {
+	&**a as *const GcCell<T> == &**b as *const GcCell<T>
+}
+
+/// Identity of a pair: its head and tail cells. Two cells holding the same
+/// pair see each other's mutations.
+pub fn pair_key(pair: &(LCell<Value>, LCell<Value>)) -> (usize, usize) {
+	let (ref h, ref t) = *pair;
+	(&**h as *const GcCell<Value> as usize, &**t as *const GcCell<Value> as usize)
+}
+
+/// A new pair holding copies of `head` and `tail` in cells of its own, so
+/// `set-head!` and `set-tail!` on it can't change anything else.
 pub fn cons(head: LCell<Value>, tail: LCell<Value>) -> LCell<Value> {
-	lcell(Value::Cons((head, tail)))
+	lcell(Value::Cons((lcell(head.borrow().clone()), lcell(tail.borrow().clone()))))
 }
 
 pub fn int(i: i32) -> LCell<Value> {
@@ -23,6 +38,29 @@ thread_local! {
 	static FALSE: LCell<Value> = lcell(Value::False);
 	static TRUE: LCell<Value> = lcell(Value::True);
 	static EOF: LCell<Value> = lcell(Value::EOF);
+	/// What print writes while output is captured.
+	static CAPTURED: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Writes a line of program output to stdout, or to the captured output.
+pub fn write_output(line: &str) {
+	CAPTURED.with(|c| match *c.borrow_mut() {
+		Some(ref mut out) => {
+			out.push_str(line);
+			out.push('\n');
+		},
+		None => println!("{}", line),
+	})
+}
+
+/// Collects program output from now on rather than writing it to stdout.
+pub fn capture_output() {
+	CAPTURED.with(|c| *c.borrow_mut() = Some(String::new()));
+}
+
+/// Stops capturing output, returning what was collected.
+pub fn captured_output() -> String {
+	CAPTURED.with(|c| c.borrow_mut().take().unwrap_or_default())
 }
 
 pub fn nil() -> LCell<Value> {
@@ -41,3 +79,10 @@ pub fn eof() -> LCell<Value> {
 	EOF.with(|eof| eof.clone())
 }
 
+/// Whether `cell` is one of the cells `nil`, `boolean` and `eof` hand out to
+/// everyone.
+pub fn is_shared_constant(cell: &LCell<Value>) -> bool {
+	let shared = |c: &LCell<Value>| same_cell(c, cell);
+	NIL.with(&shared) || TRUE.with(&shared) || FALSE.with(&shared) || EOF.with(&shared)
+}
+
@@ -0,0 +1,80 @@
+use super::*;
+
+/// The iteration protocol shared by `for`, `map` and `fold`.
+///
+/// Lists are walked pair by pair, and a tail that is a promise is forced,
+/// so streams iterate the same way. An integer n iterates 0 to n - 1. A
+/// string iterates its characters, each as a string of its own. An
+/// object whose class has a `next` method, or a function taking no
+/// arguments, is called repeatedly until it returns `EOF`. A coroutine is
+/// resumed for each value it yields, until its function returns.
+pub enum SeqIter {
+	List(LCell<Value>),
+	Range(i32, i32),
+	/// A string and the byte offset of the next character in it.
+	Chars(String, usize),
+	Calls(LCell<Value>, LCell<Value>, LCell<Bindings>),
+	Coroutine(Gc<Coroutine>, LCell<Bindings>),
+}
+
+pub fn seq_iter(v: &LCell<Value>, env: &LCell<Bindings>) -> SeqIter {
+	let vref = v.borrow();
+	match *vref {
+		Value::Nil | Value::Cons(_) | Value::Promise(_) => SeqIter::List(v.clone()),
+		Value::Int(n) => SeqIter::Range(0, n),
+		Value::Str(ref s) => SeqIter::Chars(s.clone(), 0),
+		Value::Object(ref o) => match o.class.find_method("next") {
+			Some(method) => SeqIter::Calls(method, cons(v.clone(), nil()), env.clone()),
+			None => panic!("{} is not iterable, it has no next method", o.class.name),
+		},
+		Value::Fn(ref fun, true) if fun.arity() == Some(0) => SeqIter::Calls(v.clone(), nil(), env.clone()),
+		Value::Coroutine(ref co) => SeqIter::Coroutine(co.clone(), env.clone()),
+		ref v => panic!("{} is not iterable", v.opaque()),
+	}
+}
+
+impl Iterator for SeqIter {
+	type Item = LCell<Value>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		match *self {
+			SeqIter::List(ref mut pos) => {
+				let cur = force(pos.clone());
+				let (head, tail) = match *cur.borrow() {
+					Value::Nil => return None,
+					Value::Cons((ref h, ref t)) => (h.clone(), t.clone()),
+					ref v => panic!("expected a proper list, found the improper tail {}", v.opaque()),
+				};
+				*pos = tail;
+				Some(head)
+			},
+			SeqIter::Range(ref mut i, end) => {
+				if *i < end {
+					*i += 1;
+					Some(int(*i - 1))
+				} else {
+					None
+				}
+			},
+			SeqIter::Chars(ref s, ref mut pos) => {
+				let c = s[*pos..].chars().next()?;
+				*pos += c.len_utf8();
+				Some(lcell(Value::Str(c.to_string())))
+			},
+			SeqIter::Calls(ref fun, ref args, ref env) => {
+				let v = apply(fun.clone(), args.clone(), env.clone());
+				let done = v.borrow().has_type("eof");
+				if done { None } else { Some(v) }
+			},
+			SeqIter::Coroutine(ref co, ref env) => {
+				if co.status() == Status::Dead {
+					return None;
+				}
+				match resume(co, nil(), env.clone()) {
+					CoroutineResult::Yield(v) => Some(v),
+					CoroutineResult::Return(_) => None,
+				}
+			},
+		}
+	}
+}
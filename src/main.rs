@@ -19,6 +19,13 @@ extern crate gc_derive;
 #[macro_use]
 extern crate lazy_static;
 
+extern crate corosensei;
+
+extern crate rustyline;
+
+extern crate toml;
+extern crate sha2;
+
 #[allow(unused_imports)]
 use pest::Parser;
 
@@ -26,16 +33,17 @@ use std::fmt;
 use std::rc::Rc;
 use std::iter::FromIterator;
 
-use clap::{Arg, App};
+use clap::{Arg, App, AppSettings, SubCommand};
 
-use gc::{Gc, GcCell};
+use gc::{Gc, GcCell, Trace, Finalize};
+use std::cell::RefCell;
 
 macro_rules! import_submodules {
 	($m:ident) => (mod $m; use $m::*;);
 	($m:ident, $($ms:ident),+) => (import_submodules!($m); import_submodules!($($ms),+););
 }
 
-import_submodules!(value, func, parse, internals, eval, bindings, builtins, read);
+import_submodules!(value, func, parse, internals, eval, bindings, builtins, read, record, object, generic, equal, order, printer, promise, iter, coroutine, values, parameter, freeze, doc, module, project, repl, server);
 
 const PRELUDE: &'static str = include_str!("prelude.l3");
 
@@ -53,18 +61,62 @@ pub fn run_program(program: LCell<Value>, env: LCell<Bindings>) {
 	}
 }
 
+/// Runs the project that `dir` is in, after checking its dependencies
+/// against l3.lock.
+pub fn run_project(dir: &std::path::Path) -> LCell<Bindings> {
+	let project = Project::load(&find_project(dir));
+	project.check_lock();
+	project.run()
+}
+
 fn main() {
+	// The interpreter gets a thread with a stack deep enough for the
+	// nesting `eval` allows. Its panics have been reported already.
+	let interpreter = std::thread::Builder::new()
+		.name("main".to_string())
+		.stack_size(EVAL_STACK_SIZE)
+		.spawn(run)
+		.expect("could not start the interpreter thread");
+	if interpreter.join().is_err() {
+		std::process::exit(101);
+	}
+}
+
+fn run() {
 	let opts = App::new(crate_name!())
 					.version(crate_version!())
 					.author(crate_authors!("\n"))
 					.about(crate_description!())
+					.setting(AppSettings::ArgsNegateSubcommands)
 					.arg(Arg::with_name("INPUT")
-						.help("path to L3 program to interpret")
-						.required(true))
+						.help("path to L3 program to interpret, without one an interactive session starts"))
+					.arg(Arg::with_name("listen")
+						.long("listen")
+						.value_name("ADDR")
+						.help("serves REPL sessions for editors on ADDR, host:port or the path of a Unix domain socket"))
+					.arg(Arg::with_name("isolated")
+						.long("isolated")
+						.requires("listen")
+						.help("gives each connection to the server an environment of its own"))
+					.subcommand(SubCommand::with_name("run")
+						.about("runs the project in the current directory, as described by its l3.toml"))
+					.subcommand(SubCommand::with_name("lock")
+						.about("records the dependencies of the project in the current directory in l3.lock"))
 					.get_matches();
 
-	let infile = opts.value_of("INPUT").unwrap();
-	run_program(lcell(read_program_file(infile)), loaded_env());
+	if let Some(addr) = opts.value_of("listen") {
+		std::panic::set_hook(Box::new(|info| eprintln!("error: {}", panic_message(info.payload()))));
+		return serve(addr, opts.is_present("isolated"));
+	}
+	let cwd = std::env::current_dir().expect("could not get the current directory");
+	match opts.subcommand_name() {
+		Some("run") => { run_project(&cwd); },
+		Some("lock") => Project::load(&find_project(&cwd)).write_lock(),
+		_ => match opts.value_of("INPUT") {
+			Some(infile) => run_file(infile, loaded_env()),
+			None => run_repl(),
+		},
+	}
 }
 
 #[cfg(test)]
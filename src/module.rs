@@ -0,0 +1,241 @@
+use super::*;
+use std::collections::{BTreeMap, HashMap};
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+
+/// The bindings a module exports, by the names it exports them under.
+/// Importing binds the same cells, so importers see the module's values
+/// rather than copies of them: a list, record or object the module changes
+/// in place changes for its importers too. A name the module binds again
+/// with `set` is not seen by them, since that binds a new cell.
+#[derive(Trace, Finalize)]
+pub struct Module {
+	pub name: String,
+	exports: BTreeMap<String, LCell<Value>>,
+}
+
+impl Module {
+	pub fn get(&self, name: &str) -> Option<LCell<Value>> {
+		self.exports.get(name).cloned()
+	}
+
+	pub fn names(&self) -> Vec<String> {
+		self.exports.keys().cloned().collect()
+	}
+}
+
+impl fmt::Display for Module {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#<module {}>", self.name)
+	}
+}
+
+impl fmt::Debug for Module {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Module({})", self.name)
+	}
+}
+
+thread_local! {
+	/// The modules of the files imported so far, by canonical path. Never
+	/// dropped, since the collector may be gone by the time thread locals
+	/// are destroyed.
+	static CACHE: ManuallyDrop<RefCell<HashMap<PathBuf, Gc<Module>>>> = ManuallyDrop::new(RefCell::new(HashMap::new()));
+	/// The files being loaded, innermost last.
+	static LOADING: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+	/// The source directories of the project being run.
+	static SEARCH_PATH: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+}
+
+/// Sets the directories imports are looked up in before `L3_PATH`.
+pub fn set_search_path(dirs: Vec<PathBuf>) {
+	SEARCH_PATH.with(|s| *s.borrow_mut() = dirs);
+}
+
+/// Keeps a file on the loading stack while it runs, refusing to load a
+/// file that is already on it.
+struct Loading;
+
+impl Loading {
+	fn enter(path: PathBuf) -> Loading {
+		let cycle = LOADING.with(|l| {
+			let l = l.borrow();
+			l.iter().position(|p| *p == path).map(|pos| {
+				l[pos..].iter()
+					.chain(std::iter::once(&path))
+					.map(|p| p.display().to_string())
+					.collect::<Vec<String>>()
+					.join(" -> ")
+			})
+		});
+		if let Some(cycle) = cycle {
+			panic!("import cycle {}", cycle);
+		}
+		LOADING.with(|l| l.borrow_mut().push(path));
+		Loading
+	}
+}
+
+impl Drop for Loading {
+	fn drop(&mut self) {
+		LOADING.with(|l| l.borrow_mut().pop());
+	}
+}
+
+/// Finds the file an import names. Relative paths are looked up next to
+/// the importing file, or in the working directory outside of any file,
+/// then in the search path of the project, then in each directory of
+/// `L3_PATH`.
+fn resolve(file: &str) -> PathBuf {
+	let path = Path::new(file);
+	let mut candidates = Vec::new();
+	if path.is_absolute() {
+		candidates.push(path.to_path_buf());
+	} else {
+		let base = LOADING.with(|l| l.borrow().last().and_then(|f| f.parent().map(Path::to_path_buf)));
+		candidates.push(base.unwrap_or_else(|| PathBuf::from(".")).join(path));
+		SEARCH_PATH.with(|s| candidates.extend(s.borrow().iter().map(|dir| dir.join(path))));
+		if let Some(dirs) = std::env::var_os("L3_PATH") {
+			candidates.extend(std::env::split_paths(&dirs).map(|dir| dir.join(path)));
+		}
+	}
+	candidates.into_iter()
+		.filter_map(|c| c.canonicalize().ok())
+		.find(|c| c.is_file())
+		.unwrap_or_else(|| panic!("import could not find {}", file))
+}
+
+fn path_str(path: &Path) -> &str {
+	path.to_str().unwrap_or_else(|| panic!("path is not valid unicode {}", path.display()))
+}
+
+/// Runs a program file in env, with the imports in it resolved relative to
+/// the file.
+pub fn run_file(file: &str, env: LCell<Bindings>) {
+	let path = Path::new(file).canonicalize().unwrap_or_else(|e| panic!("{}: {}", file, e));
+	let _loading = Loading::enter(path);
+	run_program(lcell(read_program_file(file)), env);
+}
+
+fn is_module_form(term: &LCell<Value>) -> bool {
+	match *term.borrow() {
+		Value::Cons((ref h, _)) => *h.borrow() == Value::Ident("module".to_string()),
+		_ => false,
+	}
+}
+
+/// Loads a file in an environment of its own. Its module is the last one
+/// it declares at the top level, or if it declares none, one exporting
+/// everything it defines.
+fn load_file(path: &Path) -> Gc<Module> {
+	let _loading = Loading::enter(path.to_path_buf());
+	let scope = lcell(make_empty_bindings(loaded_env()));
+	let mut declared = None;
+	for term in read_program_file(path_str(path)).iter() {
+		let is_module = is_module_form(&term);
+		let value = eval(term, scope.clone());
+		if is_module {
+			declared = Some(module_param(&value, "module"));
+		}
+	}
+	declared.unwrap_or_else(|| Gc::new(Module {
+		name: path.file_stem().map_or("", |s| s.to_str().unwrap_or("")).to_string(),
+		exports: scope.borrow().own_bindings(),
+	}))
+}
+
+/// The module of a file, loading it the first time it is imported.
+pub fn import_file(file: &str) -> Gc<Module> {
+	let path = resolve(file);
+	if let Some(module) = CACHE.with(|c| c.borrow().get(&path).cloned()) {
+		return module;
+	}
+	let module = load_file(&path);
+	CACHE.with(|c| c.borrow_mut().insert(path, module.clone()));
+	module
+}
+
+fn module_param(v: &LCell<Value>, what: &str) -> Gc<Module> {
+	match *v.borrow() {
+		Value::Module(ref m) => m.clone(),
+		ref v => panic!("{} got something not a module {}", what, v.opaque()),
+	}
+}
+
+fn symbol_name(v: &LCell<Value>, what: &str) -> String {
+	match *v.borrow() {
+		Value::Ident(ref i) => i.clone(),
+		ref v => panic!("{} is not an identifier {}", what, v),
+	}
+}
+
+/// `(module name (export name...) body...)` evaluates body in a fresh
+/// environment with only the builtins and the prelude, and binds name to a
+/// module exporting the given names from it. `set-global` in body binds in
+/// that environment's root, which importers don't see.
+pub fn eval_module(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let name = it.next().unwrap();
+	let module_name = symbol_name(&name, "module name");
+	let export_list = it.next().unwrap();
+	let names: Vec<String> = match *export_list.borrow() {
+		Value::Cons((ref h, _)) if *h.borrow() == Value::Ident("export".to_string()) =>
+			export_list.borrow().iter().skip(1).map(|e| symbol_name(&e, "module export")).collect(),
+		ref v => panic!("module {} needs an (export name...) list, found {}", module_name, v),
+	};
+
+	let scope = lcell(make_empty_bindings(loaded_env()));
+	eval_sequence(it, scope.clone());
+	let own = scope.borrow().own_bindings();
+	let mut exports = BTreeMap::new();
+	for export in names {
+		let v = own.get(&export).cloned()
+			.unwrap_or_else(|| panic!("module {} does not define {}", module_name, export));
+		exports.insert(export, v);
+	}
+
+	let module = lcell(Value::Module(Gc::new(Module {
+		name: module_name,
+		exports: exports,
+	})));
+	env.borrow_mut().set_binding(&*name.borrow(), module.clone());
+	module
+}
+
+/// `(import spec clause...)` binds the exports of a module in the current
+/// scope and returns the module. spec is the path of a file, which is only
+/// loaded the first time it is imported, or the name of a module. The
+/// clause `(only name...)` binds just the given exports, `(prefix p)`
+/// binds them with p in front of their names.
+pub fn eval_import(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let spec = it.next().unwrap();
+	let module = match *spec.borrow() {
+		Value::Str(ref file) => import_file(file),
+		Value::Ident(_) => module_param(&env.borrow().get_binding(&*spec.borrow()), "import"),
+		ref v => panic!("import needs a path or a module name, found {}", v.opaque()),
+	};
+
+	let mut only = None;
+	let mut prefix = String::new();
+	for clause in it {
+		let parts: Vec<LCell<Value>> = match *clause.borrow() {
+			Value::Cons(_) => clause.borrow().iter().collect(),
+			ref v => panic!("import clause is not a list {}", v),
+		};
+		match symbol_name(&parts[0], "import clause").as_str() {
+			"only" => only = Some(parts[1..].iter().map(|n| symbol_name(n, "import only")).collect()),
+			"prefix" if parts.len() == 2 => prefix = symbol_name(&parts[1], "import prefix"),
+			_ => panic!("import clause must be (only name...) or (prefix name), found {}", &*clause.borrow()),
+		}
+	}
+
+	let names: Vec<String> = only.unwrap_or_else(|| module.names());
+	let mut envref = env.borrow_mut();
+	for name in names {
+		let v = module.get(&name)
+			.unwrap_or_else(|| panic!("module {} does not export {}", module.name, name));
+		envref.set_binding(&Value::Ident(format!("{}{}", prefix, name)), v);
+	}
+	lcell(Value::Module(module))
+}
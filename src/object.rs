@@ -0,0 +1,282 @@
+use super::*;
+use std::collections::BTreeMap;
+
+#[derive(Trace, Finalize)]
+pub struct Class {
+	pub name: String,
+	pub superclass: Option<Gc<Class>>,
+	pub fields: Vec<String>,
+	pub methods: BTreeMap<String, LCell<Value>>,
+}
+
+impl Class {
+	fn is(&self, other: &Class) -> bool {
+		self as *const Class == other as *const Class
+	}
+
+	pub fn all_fields(&self) -> Vec<String> {
+		let mut fields = match self.superclass {
+			None => Vec::new(),
+			Some(ref sup) => sup.all_fields(),
+		};
+		fields.extend(self.fields.iter().cloned());
+		fields
+	}
+
+	pub fn find_method(&self, name: &str) -> Option<LCell<Value>> {
+		match self.methods.get(name) {
+			Some(m) => Some(m.clone()),
+			None => match self.superclass {
+				None => None,
+				Some(ref sup) => sup.find_method(name),
+			},
+		}
+	}
+
+	pub fn is_subclass_of(&self, other: &Class) -> bool {
+		if self.is(other) {
+			return true;
+		}
+		match self.superclass {
+			None => false,
+			Some(ref sup) => sup.is_subclass_of(other),
+		}
+	}
+}
+
+impl fmt::Debug for Class {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Class {{ name: {:?}, fields: {:?} }}", self.name, self.fields)
+	}
+}
+
+impl PartialEq for Class {
+	fn eq(&self, other: &Class) -> bool {
+		self.is(other)
+	}
+}
+
+impl fmt::Display for Class {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#<class {}>", self.name)
+	}
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct Object {
+	pub class: Gc<Class>,
+	pub fields: LCell<Bindings>,
+}
+
+impl Object {
+	fn check_field(&self, name: &str) -> Value {
+		if !self.class.all_fields().iter().any(|f| f == name) {
+			panic!("{} has no field {}", self.class.name, name)
+		}
+		Value::Ident(name.to_string())
+	}
+
+	pub fn get_field(&self, name: &str) -> LCell<Value> {
+		let id = self.check_field(name);
+		self.fields.borrow().get_binding(&id)
+	}
+
+	/// The fields of the object with their values, superclass fields first.
+	pub fn named_fields(&self) -> Vec<(String, LCell<Value>)> {
+		let fields = self.fields.borrow();
+		self.class.all_fields().into_iter()
+			.map(|name| {
+				let v = fields.get_binding(&Value::Ident(name.clone()));
+				(name, v)
+			})
+			.collect()
+	}
+
+	pub fn set_field(&self, name: &str, v: LCell<Value>) {
+		let id = self.check_field(name);
+		if is_frozen(&self.fields) {
+			panic!("cannot set field {} of a frozen {}", name, self.class.name)
+		}
+		self.fields.borrow_mut().set_binding(&id, v);
+	}
+}
+
+impl fmt::Debug for Object {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Object {{ class: {:?} }}", self.class.name)
+	}
+}
+
+impl PartialEq for Object {
+	fn eq(&self, other: &Object) -> bool {
+		&*self.fields as *const GcCell<Bindings> == &*other.fields as *const GcCell<Bindings>
+	}
+}
+
+impl fmt::Display for Object {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write_shared(&Value::Object(self.clone()), f)
+	}
+}
+
+fn ident_name(v: &LCell<Value>, what: &str) -> String {
+	match *v.borrow() {
+		Value::Ident(ref i) => i.clone(),
+		ref v => panic!("{} is not an identifier {}", what, v),
+	}
+}
+
+pub fn eval_defclass(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let name = ident_name(&it.next().unwrap(), "defclass name");
+	check_type_name(&name, "defclass");
+
+	let super_list = it.next().unwrap();
+	let mut supers = super_list.borrow().iter();
+	let superclass = match supers.next() {
+		None => None,
+		Some(s) => match *eval(s, env.clone()).borrow() {
+			Value::Class(ref c) => Some(c.clone()),
+			ref v => panic!("defclass superclass is not a class {}", v.opaque()),
+		},
+	};
+	if supers.next().is_some() {
+		panic!("defclass supports a single superclass only")
+	}
+
+	let field_list = it.next().unwrap();
+	let fields: Vec<String> = field_list.borrow().iter()
+								.map(|f| ident_name(&f, "defclass field"))
+								.collect();
+	let mut seen = match superclass {
+		None => Vec::new(),
+		Some(ref sup) => sup.all_fields(),
+	};
+	for field in fields.iter() {
+		if seen.contains(field) {
+			panic!("defclass {} has field {} more than once, counting its superclasses", name, field);
+		}
+		seen.push(field.clone());
+	}
+
+	let mut methods = BTreeMap::new();
+	for clause in it {
+		let mut clause_it = clause.borrow().iter();
+		match clause_it.next() {
+			Some(ref kw) if *kw.borrow() == Value::Ident("fn".to_string()) => {},
+			_ => panic!("defclass method clause must start with fn {}", &*clause.borrow()),
+		}
+		let method_name = ident_name(&clause_it.next().expect("defclass method without a name"), "method name");
+		let arglist = clause_it.next().expect("defclass method without an argument list");
+		let mut args = vec!["self".to_string()];
+		args.extend(arglist.borrow().iter().map(|a| ident_name(&a, "method argument")));
+		let (doc, listing) = split_docstring(clause_it.collect());
+		let method = lcell(Value::Fn(Func::NFunc(FunctionDef {
+			name: Some(method_name.clone()),
+			doc: doc,
+			args: args,
+			listing: listing,
+			env: lcell(make_empty_bindings(env.clone())),
+		}), true));
+		methods.insert(method_name, method);
+	}
+
+	let class = lcell(Value::Class(Gc::new(Class {
+		name: name.clone(),
+		superclass: superclass,
+		fields: fields,
+		methods: methods,
+	})));
+	env.borrow_mut().set_binding(&Value::Ident(name), class);
+	nil()
+}
+
+fn class_param(v: &LCell<Value>, what: &str) -> Gc<Class> {
+	match *v.borrow() {
+		Value::Class(ref c) => c.clone(),
+		ref v => panic!("{} called with something not a class {}", what, v.opaque()),
+	}
+}
+
+fn object_param(v: &LCell<Value>, what: &str) -> Object {
+	match *v.borrow() {
+		Value::Object(ref o) => o.clone(),
+		ref v => panic!("{} called with something not an object {}", what, v.opaque()),
+	}
+}
+
+pub fn fn_new(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let class = class_param(&it.next().unwrap(), "new");
+	let object = Object {
+		class: class.clone(),
+		fields: lcell(make_root_bindings(&[], &[], vec![])),
+	};
+	let fields = class.all_fields();
+	for name in fields.iter() {
+		let v = match it.next() {
+			None => nil(),
+			Some(v) => v,
+		};
+		object.set_field(name, v);
+	}
+	if it.next().is_some() {
+		panic!("new called with more values than {} has fields", class.name)
+	}
+	lcell(Value::Object(object))
+}
+
+pub fn fn_send(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let receiver = it.next().unwrap();
+	let object = object_param(&receiver, "send");
+	let selector = ident_name(&it.next().unwrap(), "send method name");
+	let method = object.class.find_method(&selector)
+		.unwrap_or_else(|| panic!("{} does not understand {}", object.class.name, selector));
+	let args = cons(receiver, lcell(it.collect()));
+	apply(method, args, env)
+}
+
+pub fn fn_get_field(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let object = object_param(&it.next().unwrap(), "get-field");
+	let name = ident_name(&it.next().unwrap(), "field name");
+	object.get_field(&name)
+}
+
+pub fn fn_set_field(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let object = object_param(&it.next().unwrap(), "set-field!");
+	let name = ident_name(&it.next().unwrap(), "field name");
+	let v = it.next().unwrap();
+	object.set_field(&name, v.clone());
+	v
+}
+
+pub fn fn_is_a(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let v = it.next().unwrap();
+	let class = class_param(&it.next().unwrap(), "is-a?");
+	let vref = v.borrow();
+	boolean(match *vref {
+		Value::Object(ref o) => o.class.is_subclass_of(&class),
+		_ => false,
+	})
+}
+
+pub fn fn_class_of(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let first = params.borrow().iter().next().unwrap();
+	let object = object_param(&first, "class-of");
+	lcell(Value::Class(object.class.clone()))
+}
+
+pub fn fn_responds_to(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let v = it.next().unwrap();
+	let selector = ident_name(&it.next().unwrap(), "method name");
+	let vref = v.borrow();
+	boolean(match *vref {
+		Value::Object(ref o) => o.class.find_method(&selector).is_some(),
+		_ => false,
+	})
+}
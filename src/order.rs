@@ -0,0 +1,82 @@
+use super::*;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+fn type_rank(v: &Value) -> u8 {
+	match *v {
+		Value::Nil => 0,
+		Value::False | Value::True => 1,
+		Value::Int(_) => 2,
+		Value::Ident(_) => 3,
+		Value::Str(_) => 4,
+		Value::Cons(_) => 5,
+		Value::Record(_) => 6,
+		Value::Fn(_, _) => 7,
+		Value::Class(_) => 8,
+		Value::Object(_) => 9,
+		Value::Promise(_) => 10,
+		Value::Coroutine(_) => 11,
+		Value::Module(_) => 12,
+		Value::EOF => 13,
+	}
+}
+
+fn addr<T>(v: &T) -> usize {
+	v as *const T as usize
+}
+
+/// Total order over all values.
+///
+/// Values of different types are ordered by type: nil, booleans, integers,
+/// symbols, strings, pairs, records, functions, classes, objects, promises,
+/// coroutines, modules and EOF. Booleans order false first, integers numerically,
+/// symbols by name and strings by contents. Lists compare lexicographically, so a proper prefix
+/// sorts first. Records of the same type compare field by field, records of
+/// different types by type name. Functions, classes, objects, promises,
+/// coroutines and modules have no natural order and are ordered by identity, which is
+/// consistent within a run but not between runs. Cyclic structure is
+/// handled the same way as by `is_equal`.
+pub fn compare(a: &LCell<Value>, b: &LCell<Value>) -> Ordering {
+	let mut seen = HashSet::new();
+	let mut stack = vec![(a.clone(), b.clone())];
+	while let Some((a, b)) = stack.pop() {
+		if same_cell(&a, &b) || !seen.insert((addr(&*a), addr(&*b))) {
+			continue;
+		}
+		let (aref, bref) = (a.borrow(), b.borrow());
+		let ord = match (&*aref, &*bref) {
+			(&Value::False, &Value::True) => Ordering::Less,
+			(&Value::True, &Value::False) => Ordering::Greater,
+			(&Value::Int(x), &Value::Int(y)) => x.cmp(&y),
+			(&Value::Ident(ref x), &Value::Ident(ref y)) |
+			(&Value::Str(ref x), &Value::Str(ref y)) => x.cmp(y),
+			(&Value::Cons((ref ah, ref at)), &Value::Cons((ref bh, ref bt))) => {
+				stack.push((at.clone(), bt.clone()));
+				stack.push((ah.clone(), bh.clone()));
+				Ordering::Equal
+			},
+			(&Value::Record(ref x), &Value::Record(ref y)) => {
+				if addr(&*x.rtype) == addr(&*y.rtype) {
+					for (xf, yf) in x.field_cells().into_iter().zip(y.field_cells()).rev() {
+						stack.push((xf, yf));
+					}
+					Ordering::Equal
+				} else {
+					x.rtype.name.cmp(&y.rtype.name)
+						.then(addr(&*x.rtype).cmp(&addr(&*y.rtype)))
+				}
+			},
+			(&Value::Fn(ref x, xev), &Value::Fn(ref y, yev)) => x.identity().cmp(&y.identity()).then(xev.cmp(&yev)),
+			(&Value::Object(ref x), &Value::Object(ref y)) => addr(&*x.fields).cmp(&addr(&*y.fields)),
+			(&Value::Class(ref x), &Value::Class(ref y)) => addr(&**x).cmp(&addr(&**y)),
+			(&Value::Promise(ref x), &Value::Promise(ref y)) => addr(&**x).cmp(&addr(&**y)),
+			(&Value::Coroutine(ref x), &Value::Coroutine(ref y)) => addr(&**x).cmp(&addr(&**y)),
+			(&Value::Module(ref x), &Value::Module(ref y)) => addr(&**x).cmp(&addr(&**y)),
+			(x, y) => type_rank(x).cmp(&type_rank(y)),
+		};
+		if ord != Ordering::Equal {
+			return ord;
+		}
+	}
+	Ordering::Equal
+}
@@ -0,0 +1,84 @@
+use super::*;
+
+/// A dynamically scoped value. Calling the parameter returns its current
+/// value, which `parameterize` replaces for the dynamic extent of its body.
+/// Parameters are bound outside the lexical environment, so a function sees
+/// the value of whoever called it rather than of where it was defined.
+#[derive(Trace, Finalize)]
+pub struct Parameter {
+	value: LCell<Value>,
+	converter: Option<LCell<Value>>,
+}
+
+impl Parameter {
+	pub fn eval(&self, params: LCell<Value>) -> LCell<Value> {
+		if params.borrow().iter().next().is_some() {
+			panic!("parameter called with arguments {}", &*params.borrow());
+		}
+		self.value.clone()
+	}
+
+	fn convert(&self, v: LCell<Value>, env: &LCell<Bindings>) -> LCell<Value> {
+		match self.converter {
+			Some(ref conv) => apply(conv.clone(), cons(v, nil()), env.clone()),
+			None => v,
+		}
+	}
+}
+
+/// Puts the values replaced by `parameterize` back when it is left, by
+/// returning normally or by a panic unwinding through it.
+struct Restore {
+	saved: Vec<(LCell<Parameter>, LCell<Value>)>,
+}
+
+impl Drop for Restore {
+	fn drop(&mut self) {
+		for &(ref param, ref old) in self.saved.iter().rev() {
+			param.borrow_mut().value = old.clone();
+		}
+	}
+}
+
+/// `(make-parameter value)` or `(make-parameter value converter)`. The
+/// converter is applied to the initial value and to every value the
+/// parameter is given by `parameterize`.
+pub fn fn_make_parameter(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let init = it.next().unwrap();
+	let mut param = Parameter {
+		value: nil(),
+		converter: it.next(),
+	};
+	param.value = param.convert(init, &env);
+	lcell(Value::Fn(Func::Parameter(lcell(param)), true))
+}
+
+fn parameter_of(v: &LCell<Value>) -> LCell<Parameter> {
+	match *v.borrow() {
+		Value::Fn(Func::Parameter(ref p), _) => p.clone(),
+		ref v => panic!("parameterize called with something not a parameter {}", v.opaque()),
+	}
+}
+
+/// `(parameterize ((param value)...) body...)` evaluates all the parameters
+/// and values first, then gives the parameters those values while body
+/// runs.
+pub fn eval_parameterize(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let bindings = it.next().unwrap();
+	let mut new_values = Vec::new();
+	for binding in bindings.borrow().iter() {
+		let mut parts = binding.borrow().iter();
+		let param = parameter_of(&eval(parts.next().expect("parameterize binding without a parameter"), env.clone()));
+		let value = eval(parts.next().expect("parameterize binding without a value"), env.clone());
+		let value = param.borrow().convert(value, &env);
+		new_values.push((param, value));
+	}
+	let mut restore = Restore { saved: Vec::new() };
+	for (param, value) in new_values {
+		let old = std::mem::replace(&mut param.borrow_mut().value, value);
+		restore.saved.push((param, old));
+	}
+	eval_sequence(it, env)
+}
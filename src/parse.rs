@@ -1,4 +1,5 @@
 use pest::inputs::Input;
+use std::collections::HashMap;
 use super::*;
 
 #[cfg(debug_assertions)]
@@ -14,7 +15,9 @@ pub fn parse_program<I: Input>(pairs: pest::iterators::Pairs<Rule, I>) -> Value
 		for list in program.into_inner() {
 			match list.as_rule() {
 				Rule::list => {
-					builder.push(lcell(parse_list_inner(list.into_inner())));
+					let mut reader = DatumReader::new();
+					builder.push_cell(reader.list(list.into_inner()));
+					reader.finish();
 				}
 				_ => panic!("program contains non-list value at top level")
 			}
@@ -23,32 +26,131 @@ pub fn parse_program<I: Input>(pairs: pest::iterators::Pairs<Rule, I>) -> Value
 	builder.build()
 }
 
-pub fn parse_list_inner<I: Input>(pairs: pest::iterators::Pairs<Rule, I>) -> Value {
-	let mut builder = ListBuilder::new();
-	for pair in pairs {
-		match pair.as_rule() {
-			Rule::term => {
-				builder.push(lcell(parse(pair.into_inner())));
-			},
-			v => panic!("something fishy came along in a list {:?}", v),
+/// The terms of an `input`, each read on its own like the lists of a
+/// program.
+pub fn parse_input<I: Input>(pairs: pest::iterators::Pairs<Rule, I>) -> Vec<LCell<Value>> {
+	let mut terms = Vec::new();
+	for input in pairs {
+		for term in input.into_inner() {
+			let mut reader = DatumReader::new();
+			terms.push(reader.term(term.into_inner()));
+			reader.finish();
 		}
 	}
-	builder.build()
+	terms
 }
 
 pub fn parse<I: Input>(pairs: pest::iterators::Pairs<Rule, I>) -> Value {
-	for pair in pairs {
-		match pair.as_rule() {
-			Rule::term => {
-				return parse(pair.into_inner())
-			}
-			Rule::list => {
-				return parse_list_inner(pair.into_inner())
+	let mut reader = DatumReader::new();
+	let term = reader.term(pairs);
+	reader.finish();
+	let v = term.borrow().clone();
+	v
+}
+
+/// The contents of a string literal, without its quotes and with escapes
+/// replaced by the characters they stand for.
+pub fn unescape(literal: &str) -> String {
+	let mut s = String::new();
+	let mut chars = literal[1..literal.len() - 1].chars();
+	while let Some(c) = chars.next() {
+		s.push(match c {
+			'\\' => match chars.next() {
+				Some('n') => '\n',
+				Some('t') => '\t',
+				Some(c) => c,
+				None => unreachable!(),
 			},
-			Rule::integer => return Value::Int(pair.into_span().as_str().parse().unwrap()),
-			Rule::ident => return Value::Ident(String::from(pair.into_span().as_str())),
-			v => panic!("wtf: {:?}", v),
+			c => c,
+		});
+	}
+	s
+}
+
+/// Reads one datum, resolving `#n=` labels and `#n#` references. A
+/// reference to a label whose list is still being read gets a placeholder
+/// cell that is filled in once the list is complete, which is how cycles
+/// are built.
+struct DatumReader {
+	labels: HashMap<String, LCell<Value>>,
+	pending: HashMap<String, Vec<LCell<Value>>>,
+}
+
+impl DatumReader {
+	fn new() -> DatumReader {
+		DatumReader {
+			labels: HashMap::new(),
+			pending: HashMap::new(),
+		}
+	}
+
+	/// Checks that every referenced label was defined.
+	fn finish(self) {
+		if let Some(name) = self.pending.keys().next() {
+			panic!("reference to undefined datum label #{}#", name)
+		}
+	}
+
+	fn label_name(text: &str) -> String {
+		text[1..text.len() - 1].to_string()
+	}
+
+	fn list<I: Input>(&mut self, mut pairs: pest::iterators::Pairs<Rule, I>) -> LCell<Value> {
+		let mut builder = ListBuilder::new();
+		let mut label = None;
+		while let Some(pair) = pairs.next() {
+			match pair.as_rule() {
+				Rule::label_def => {
+					label = Some(DatumReader::label_name(pair.into_span().as_str()));
+				},
+				Rule::term => {
+					builder.push_cell(self.term(pair.into_inner()));
+				},
+				Rule::dot => {
+					let tail = pairs.next().expect("dotted list without a tail");
+					let tail = self.term(tail.into_inner());
+					return self.define(label, lcell(builder.build_with_tail(tail)));
+				},
+				v => panic!("something fishy came along in a list {:?}", v),
+			}
+		}
+		self.define(label, lcell(builder.build()))
+	}
+
+	fn define(&mut self, label: Option<String>, cell: LCell<Value>) -> LCell<Value> {
+		if let Some(name) = label {
+			let v = cell.borrow().clone();
+			for placeholder in self.pending.remove(&name).unwrap_or_else(Vec::new) {
+				*placeholder.borrow_mut() = v.clone();
+			}
+			self.labels.insert(name, cell.clone());
+		}
+		cell
+	}
+
+	fn reference(&mut self, name: String) -> LCell<Value> {
+		match self.labels.get(&name) {
+			Some(cell) => return lcell(cell.borrow().clone()),
+			None => {},
+		}
+		let placeholder = lcell(Value::Nil);
+		self.pending.entry(name).or_insert_with(Vec::new).push(placeholder.clone());
+		placeholder
+	}
+
+	fn term<I: Input>(&mut self, pairs: pest::iterators::Pairs<Rule, I>) -> LCell<Value> {
+		for pair in pairs {
+			let v = match pair.as_rule() {
+				Rule::term => return self.term(pair.into_inner()),
+				Rule::list => return self.list(pair.into_inner()),
+				Rule::label_ref => return self.reference(DatumReader::label_name(pair.into_span().as_str())),
+				Rule::integer => Value::Int(pair.into_span().as_str().parse().unwrap()),
+				Rule::string => Value::Str(unescape(pair.into_span().as_str())),
+				Rule::ident => Value::Ident(String::from(pair.into_span().as_str())),
+				v => panic!("wtf: {:?}", v),
+			};
+			return lcell(v);
 		}
+		unreachable!()
 	}
-	unreachable!()
 }
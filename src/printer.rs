@@ -0,0 +1,160 @@
+use super::*;
+use std::collections::HashMap;
+
+/// What the printer labels when it meets it more than once: a pair, or the
+/// cell the fields of a record or an object are kept in.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NodeKey {
+	Pair(usize, usize),
+	Cell(usize),
+}
+
+fn node_key(v: &Value) -> Option<NodeKey> {
+	match *v {
+		Value::Cons(ref pair) => {
+			let (h, t) = pair_key(pair);
+			Some(NodeKey::Pair(h, t))
+		},
+		Value::Record(ref r) => Some(NodeKey::Cell(&*r.fields as *const GcCell<Vec<LCell<Value>>> as usize)),
+		Value::Object(ref o) => Some(NodeKey::Cell(&*o.fields as *const GcCell<Bindings> as usize)),
+		_ => None,
+	}
+}
+
+/// The fields of a record or an object, which are printed as
+/// `#<name field: value...>`.
+fn named_fields(v: &Value) -> Option<(String, Vec<(String, LCell<Value>)>)> {
+	match *v {
+		Value::Record(ref r) => Some((
+			r.rtype.name.clone(),
+			r.rtype.fields.iter().cloned().zip(r.field_cells()).collect(),
+		)),
+		Value::Object(ref o) => Some((o.class.name.clone(), o.named_fields())),
+		_ => None,
+	}
+}
+
+struct SharedPrinter {
+	shared: HashMap<NodeKey, Option<usize>>,
+	next_label: usize,
+}
+
+impl SharedPrinter {
+	/// Finds every pair, record and object reachable more than once from
+	/// `root`.
+	fn new(root: &LCell<Value>) -> SharedPrinter {
+		let mut seen = HashMap::new();
+		let mut stack = vec![root.clone()];
+		while let Some(cell) = stack.pop() {
+			let v = cell.borrow().clone();
+			if let Some(key) = node_key(&v) {
+				let count = seen.entry(key).or_insert(0);
+				*count += 1;
+				if *count > 1 {
+					continue;
+				}
+			}
+			match v {
+				Value::Cons((h, t)) => {
+					stack.push(t);
+					stack.push(h);
+				},
+				ref v => if let Some((_, fields)) = named_fields(v) {
+					stack.extend(fields.into_iter().rev().map(|(_, c)| c));
+				},
+			}
+		}
+		SharedPrinter {
+			shared: seen.into_iter()
+				.filter(|&(_, count)| count > 1)
+				.map(|(key, _)| (key, None))
+				.collect(),
+			next_label: 0,
+		}
+	}
+
+	fn is_shared(&self, pair: &(LCell<Value>, LCell<Value>)) -> bool {
+		let (h, t) = pair_key(pair);
+		self.shared.contains_key(&NodeKey::Pair(h, t))
+	}
+
+	/// Writes `root` using an explicit stack of pending work, so neither long
+	/// nor deeply nested lists grow the Rust stack.
+	fn write(&mut self, root: &LCell<Value>, f: &mut fmt::Formatter) -> fmt::Result {
+		enum Task {
+			Datum(LCell<Value>),
+			Tail(LCell<Value>),
+			Text(String),
+			Close(&'static str),
+		}
+		let mut tasks = vec![Task::Datum(root.clone())];
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Datum(cell) => {
+					let v = cell.borrow().clone();
+					if let Some(key) = node_key(&v) {
+						if let Some(label) = self.shared.get_mut(&key) {
+							match *label {
+								Some(n) => {
+									write!(f, "#{}#", n)?;
+									continue;
+								},
+								None => {
+									*label = Some(self.next_label);
+									write!(f, "#{}=", self.next_label)?;
+									self.next_label += 1;
+								},
+							}
+						}
+					}
+					match v {
+						Value::Cons((h, t)) => {
+							write!(f, "(")?;
+							tasks.push(Task::Close(")"));
+							tasks.push(Task::Tail(t));
+							tasks.push(Task::Datum(h));
+						},
+						ref v => match named_fields(v) {
+							Some((name, fields)) => {
+								write!(f, "#<{}", name)?;
+								tasks.push(Task::Close(">"));
+								for (field, c) in fields.into_iter().rev() {
+									tasks.push(Task::Datum(c));
+									tasks.push(Task::Text(format!(" {}: ", field)));
+								}
+							},
+							None => write!(f, "{}", v)?,
+						},
+					}
+				},
+				Task::Tail(cell) => {
+					let v = cell.borrow().clone();
+					match v {
+						Value::Nil => {},
+						Value::Cons(ref pair) if !self.is_shared(pair) => {
+							write!(f, " ")?;
+							tasks.push(Task::Tail(pair.1.clone()));
+							tasks.push(Task::Datum(pair.0.clone()));
+						},
+						_ => {
+							write!(f, " . ")?;
+							tasks.push(Task::Datum(cell));
+						},
+					}
+				},
+				Task::Text(s) => write!(f, "{}", s)?,
+				Task::Close(s) => write!(f, "{}", s)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Writes a list, record or object, labelling every pair, record and
+/// object that is reachable more than once with `#n=` on its first
+/// occurrence and `#n#` after that, so shared and cyclic structure is
+/// printed in finite space and lists can be read back with the same shape.
+pub fn write_shared(v: &Value, f: &mut fmt::Formatter) -> fmt::Result {
+	let root = lcell(v.clone());
+	SharedPrinter::new(&root).write(&root, f)
+}
@@ -0,0 +1,333 @@
+use super::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use sha2::{Digest, Sha256};
+use toml::{Table, Value as Toml};
+
+pub const MANIFEST: &'static str = "l3.toml";
+pub const LOCK: &'static str = "l3.lock";
+
+/// What the l3.toml of a project declares:
+///
+/// ```toml
+/// [project]
+/// name = "app"
+/// entry = "src/main.l3"
+/// sources = ["src"]
+///
+/// [dependencies]
+/// geometry = { path = "../geometry" }
+/// ```
+///
+/// Sources default to `["src"]`. Dependencies are other projects, found by
+/// their path relative to this one.
+#[derive(Clone, Debug)]
+pub struct Manifest {
+	pub name: String,
+	pub entry: PathBuf,
+	pub sources: Vec<PathBuf>,
+	pub dependencies: Vec<(String, PathBuf)>,
+}
+
+pub fn parse_toml(text: &str, what: &str) -> Table {
+	text.parse().unwrap_or_else(|e| panic!("{}: {}", what, e))
+}
+
+fn toml_string(s: &str) -> String {
+	Toml::String(s.to_string()).to_string()
+}
+
+/// The SHA-256 of data in hex, as l3.lock records source files.
+pub fn sha256_hex(data: &[u8]) -> String {
+	Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn manifest_string(table: &Toml, key: &str, what: &str) -> Option<String> {
+	table.get(key).map(|v| {
+		v.as_str().unwrap_or_else(|| panic!("{}: {} must be a string", what, key)).to_string()
+	})
+}
+
+pub fn read_manifest(dir: &Path) -> Manifest {
+	let file = dir.join(MANIFEST);
+	let what = file.display().to_string();
+	let text = fs::read_to_string(&file).unwrap_or_else(|e| panic!("{}: {}", what, e));
+	let doc = parse_toml(&text, &what);
+
+	let project = doc.get("project").unwrap_or_else(|| panic!("{}: missing [project]", what));
+	let name = manifest_string(project, "name", &what)
+		.unwrap_or_else(|| panic!("{}: missing name in [project]", what));
+	let entry = manifest_string(project, "entry", &what)
+		.unwrap_or_else(|| panic!("{}: missing entry in [project]", what));
+	let sources = match project.get("sources") {
+		None => vec![PathBuf::from("src")],
+		Some(v) => v.as_array()
+			.and_then(|a| a.iter().map(|s| s.as_str().map(PathBuf::from)).collect())
+			.unwrap_or_else(|| panic!("{}: sources must be an array of strings", what)),
+	};
+
+	let mut dependencies = Vec::new();
+	if let Some(deps) = doc.get("dependencies") {
+		let deps = deps.as_table().unwrap_or_else(|| panic!("{}: dependencies must be a table", what));
+		for (dep, spec) in deps {
+			let path = spec.get("path").and_then(Toml::as_str)
+				.unwrap_or_else(|| panic!("{}: dependency {} needs a path", what, dep));
+			dependencies.push((dep.clone(), PathBuf::from(path)));
+		}
+	}
+
+	Manifest {
+		name: name,
+		entry: PathBuf::from(entry),
+		sources: sources,
+		dependencies: dependencies,
+	}
+}
+
+/// Removes `.` and resolves `..` against the components before it, without
+/// looking at the file system.
+fn normalize(path: &Path) -> PathBuf {
+	let mut out = PathBuf::new();
+	for c in path.components() {
+		match c {
+			Component::CurDir => {},
+			Component::ParentDir => match out.components().next_back() {
+				Some(Component::Normal(_)) => { out.pop(); },
+				_ => out.push(".."),
+			},
+			c => out.push(c.as_os_str()),
+		}
+	}
+	if out.as_os_str().is_empty() {
+		PathBuf::from(".")
+	} else {
+		out
+	}
+}
+
+fn l3_files(dir: &Path, files: &mut Vec<PathBuf>) {
+	let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+		.unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+		.map(|e| e.unwrap_or_else(|e| panic!("{}: {}", dir.display(), e)).path())
+		.collect();
+	entries.sort();
+	for path in entries {
+		if path.is_dir() {
+			l3_files(&path, files);
+		} else if path.extension().map_or(false, |e| e == "l3") {
+			files.push(path);
+		}
+	}
+}
+
+/// A project or one of its dependencies.
+pub struct Package {
+	pub manifest: Manifest,
+	/// Where the package is, relative to the root project.
+	pub path: PathBuf,
+	pub dir: PathBuf,
+}
+
+impl Package {
+	pub fn source_dirs(&self) -> Vec<PathBuf> {
+		self.manifest.sources.iter().map(|s| self.dir.join(s)).collect()
+	}
+
+	/// The SHA-256 of each L3 file in the source directories, by its path
+	/// relative to the package.
+	fn source_hashes(&self) -> BTreeMap<String, String> {
+		let mut files = Vec::new();
+		for dir in self.source_dirs() {
+			l3_files(&dir, &mut files);
+		}
+		files.into_iter().map(|file| {
+			let contents = fs::read(&file).unwrap_or_else(|e| panic!("{}: {}", file.display(), e));
+			let name = file.strip_prefix(&self.dir).unwrap().to_string_lossy().into_owned();
+			(name, sha256_hex(&contents))
+		}).collect()
+	}
+}
+
+/// A dependency as l3.lock records it.
+#[derive(Debug, PartialEq)]
+struct Locked {
+	name: String,
+	path: String,
+	files: BTreeMap<String, String>,
+}
+
+/// A project with all its dependencies resolved, the project itself first.
+pub struct Project {
+	pub packages: Vec<Package>,
+}
+
+impl Project {
+	pub fn load(dir: &Path) -> Project {
+		let dir = dir.canonicalize().unwrap_or_else(|e| panic!("{}: {}", dir.display(), e));
+		let mut packages = Vec::new();
+		resolve(dir, PathBuf::from("."), None, &mut Vec::new(), &mut packages);
+		Project {
+			packages: packages,
+		}
+	}
+
+	pub fn root(&self) -> &Package {
+		&self.packages[0]
+	}
+
+	/// Where imports are looked up: the source directories of the project,
+	/// then those of its dependencies.
+	pub fn search_path(&self) -> Vec<PathBuf> {
+		self.packages.iter().flat_map(|p| p.source_dirs()).collect()
+	}
+
+	fn locked(&self) -> Vec<Locked> {
+		self.packages[1..].iter().map(|p| Locked {
+			name: p.manifest.name.clone(),
+			path: p.path.to_string_lossy().into_owned(),
+			files: p.source_hashes(),
+		}).collect()
+	}
+
+	fn lock_file(&self) -> PathBuf {
+		self.root().dir.join(LOCK)
+	}
+
+	pub fn write_lock(&self) {
+		let mut text = String::from("# Written by `l3 lock` and `l3 run`, do not edit.\n");
+		for dep in self.locked() {
+			text.push_str("\n[[dependency]]\n");
+			text.push_str(&format!("name = {}\n", toml_string(&dep.name)));
+			text.push_str(&format!("path = {}\n", toml_string(&dep.path)));
+			text.push_str("files = [\n");
+			for (file, hash) in dep.files.iter() {
+				text.push_str(&format!("\t{{ file = {}, sha256 = {} }},\n", toml_string(file), toml_string(hash)));
+			}
+			text.push_str("]\n");
+		}
+		let file = self.lock_file();
+		fs::write(&file, text).unwrap_or_else(|e| panic!("{}: {}", file.display(), e));
+	}
+
+	fn read_lock(&self) -> Option<Vec<Locked>> {
+		let file = self.lock_file();
+		let what = file.display().to_string();
+		let text = match fs::read_to_string(&file) {
+			Ok(text) => text,
+			Err(_) => return None,
+		};
+		let doc = parse_toml(&text, &what);
+		let bad = || -> ! { panic!("{}: malformed lock file", what) };
+		let deps = match doc.get("dependency") {
+			None => return Some(Vec::new()),
+			Some(deps) => deps.as_array().unwrap_or_else(|| bad()),
+		};
+		Some(deps.iter().map(|dep| {
+			let field = |key: &str| dep.get(key).and_then(Toml::as_str).unwrap_or_else(|| bad()).to_string();
+			let files = dep.get("files").and_then(Toml::as_array).unwrap_or_else(|| bad());
+			Locked {
+				name: field("name"),
+				path: field("path"),
+				files: files.iter().map(|f| {
+					let file_field = |key: &str| f.get(key).and_then(Toml::as_str).unwrap_or_else(|| bad()).to_string();
+					(file_field("file"), file_field("sha256"))
+				}).collect(),
+			}
+		}).collect())
+	}
+
+	/// Checks that the dependencies are what l3.lock says they are, or
+	/// writes l3.lock if there is none yet.
+	pub fn check_lock(&self) {
+		let locked = match self.read_lock() {
+			None => return self.write_lock(),
+			Some(locked) => locked,
+		};
+		let current = self.locked();
+		if let Some(reason) = lock_difference(&locked, &current) {
+			panic!("{} is out of date: {}. Run `l3 lock` to update it", self.lock_file().display(), reason);
+		}
+	}
+
+	/// Runs the entry point of the project, with imports looked up in the
+	/// source directories of the project and its dependencies. Returns the
+	/// environment it ran in.
+	pub fn run(&self) -> LCell<Bindings> {
+		set_search_path(self.search_path());
+		let entry = self.root().dir.join(&self.root().manifest.entry);
+		let env = loaded_env();
+		run_file(&entry.to_string_lossy(), env.clone());
+		env
+	}
+}
+
+/// Describes the first way the locked dependencies differ from the current
+/// ones.
+fn lock_difference(locked: &[Locked], current: &[Locked]) -> Option<String> {
+	for dep in current {
+		let old = match locked.iter().find(|l| l.name == dep.name) {
+			None => return Some(format!("dependency {} is new", dep.name)),
+			Some(old) => old,
+		};
+		if old.path != dep.path {
+			return Some(format!("dependency {} moved from {} to {}", dep.name, old.path, dep.path));
+		}
+		for (file, hash) in dep.files.iter() {
+			match old.files.get(file) {
+				None => return Some(format!("{} of {} is new", file, dep.name)),
+				Some(old_hash) if old_hash != hash => return Some(format!("{} of {} changed", file, dep.name)),
+				Some(_) => {},
+			}
+		}
+		if let Some(file) = old.files.keys().find(|f| !dep.files.contains_key(*f)) {
+			return Some(format!("{} of {} was removed", file, dep.name));
+		}
+	}
+	locked.iter()
+		.find(|l| !current.iter().any(|c| c.name == l.name))
+		.map(|l| format!("dependency {} is no longer used", l.name))
+}
+
+/// Adds the package in `dir` and, depth first, its dependencies, each only
+/// once. `chain` holds the packages depending on this one, to detect
+/// dependency cycles.
+fn resolve(dir: PathBuf, path: PathBuf, expected: Option<&str>, chain: &mut Vec<PathBuf>, packages: &mut Vec<Package>) {
+	if let Some(pos) = chain.iter().position(|d| *d == dir) {
+		let cycle: Vec<String> = chain[pos..].iter()
+			.chain(std::iter::once(&dir))
+			.map(|d| d.display().to_string())
+			.collect();
+		panic!("dependency cycle {}", cycle.join(" -> "));
+	}
+	if packages.iter().any(|p| p.dir == dir) {
+		return;
+	}
+	let manifest = read_manifest(&dir);
+	if let Some(name) = expected {
+		if manifest.name != name {
+			panic!("dependency {} at {} is named {}", name, path.display(), manifest.name);
+		}
+	}
+	let dependencies = manifest.dependencies.clone();
+	packages.push(Package {
+		manifest: manifest,
+		path: path.clone(),
+		dir: dir.clone(),
+	});
+	chain.push(dir.clone());
+	for (name, dep_path) in dependencies {
+		let dep_dir = dir.join(&dep_path).canonicalize()
+			.unwrap_or_else(|e| panic!("dependency {} at {}: {}", name, dep_path.display(), e));
+		resolve(dep_dir, normalize(&path.join(&dep_path)), Some(&name), chain, packages);
+	}
+	chain.pop();
+}
+
+/// The nearest directory from `start` up that has an l3.toml.
+pub fn find_project(start: &Path) -> PathBuf {
+	start.ancestors()
+		.find(|dir| dir.join(MANIFEST).is_file())
+		.map(Path::to_path_buf)
+		.unwrap_or_else(|| panic!("no {} in {} or any of its parents", MANIFEST, start.display()))
+}
@@ -0,0 +1,246 @@
+use super::*;
+
+#[derive(Trace, Finalize)]
+pub enum Promise {
+	/// Body expressions to evaluate in an environment.
+	Delayed(LCell<Value>, LCell<Bindings>),
+	/// A function to apply to already evaluated arguments.
+	Call(LCell<Value>, LCell<Value>, LCell<Bindings>),
+	Forced(LCell<Value>),
+}
+
+impl fmt::Display for Promise {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Promise::Forced(ref v) => write!(f, "#<promise {}>", &*v.borrow()),
+			_ => write!(f, "#<promise>"),
+		}
+	}
+}
+
+/// Returns the value of a promise, computing it the first time and
+/// remembering it after. Anything that is not a promise is its own value.
+pub fn force(v: LCell<Value>) -> LCell<Value> {
+	let promise = match *v.borrow() {
+		Value::Promise(ref p) => p.clone(),
+		_ => return v.clone(),
+	};
+	let result = match *promise.borrow() {
+		Promise::Forced(ref v) => return v.clone(),
+		Promise::Delayed(ref body, ref env) => (body.clone(), None, env.clone()),
+		Promise::Call(ref fun, ref args, ref env) => (fun.clone(), Some(args.clone()), env.clone()),
+	};
+	let value = match result {
+		(body, None, env) => eval_sequence(body.borrow().iter(), env),
+		(fun, Some(args), env) => apply(fun, args, env),
+	};
+	// forcing the body may have forced this promise already
+	if let Promise::Forced(ref v) = *promise.borrow() {
+		return v.clone();
+	}
+	*promise.borrow_mut() = Promise::Forced(value.clone());
+	value
+}
+
+fn promise(p: Promise) -> LCell<Value> {
+	lcell(Value::Promise(lcell(p)))
+}
+
+/// Registrations for the functions computing the rest of a stream, which
+/// are only ever called by forcing a promise.
+macro_rules! stream_rest {
+	($static:ident, $name:expr, $fun:ident) => (
+		static $static: Builtin = Builtin {
+			name: $name, fun: $fun, min_args: 2, max_args: Some(2), arg_types: &[],
+			args: "", doc: "Computes the rest of a stream.",
+		};
+	)
+}
+
+stream_rest!(STREAM_MAP_REST, "stream-map", fn_stream_map_rest);
+stream_rest!(STREAM_FILTER_REST, "stream-filter", fn_stream_filter_rest);
+stream_rest!(ITERATE_REST, "iterate", fn_iterate_rest);
+
+fn call_promise(fun: &'static Builtin, args: Vec<LCell<Value>>, env: &LCell<Bindings>) -> LCell<Value> {
+	promise(Promise::Call(
+		lcell(Value::Fn(Func::HFunc(fun), true)),
+		lcell(args.into_iter().collect()),
+		env.clone()))
+}
+
+pub fn eval_delay(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let expr = arguments.borrow().iter().next().unwrap();
+	promise(Promise::Delayed(cons(expr, nil()), env))
+}
+
+pub fn eval_lazy_seq(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	promise(Promise::Delayed(arguments, env))
+}
+
+pub fn eval_stream_cons(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let head = eval(it.next().unwrap(), env.clone());
+	let tail = it.next().unwrap();
+	cons(head, promise(Promise::Delayed(cons(tail, nil()), env)))
+}
+
+pub fn fn_force(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	force(params.borrow().iter().next().unwrap())
+}
+
+pub fn fn_make_promise(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let v = params.borrow().iter().next().unwrap();
+	let is_promise = v.borrow().has_type("promise");
+	if is_promise {
+		v
+	} else {
+		promise(Promise::Forced(v))
+	}
+}
+
+/// Forces v until it is no longer a promise. Streams may be given as a
+/// promise of one, as `lazy-seq` makes them, and so may their tails.
+fn force_stream(mut v: LCell<Value>) -> LCell<Value> {
+	while v.borrow().has_type("promise") {
+		v = force(v);
+	}
+	v
+}
+
+fn stream_tail(s: &LCell<Value>, name: &str) -> LCell<Value> {
+	let s = force_stream(s.clone());
+	let tail = match *s.borrow() {
+		Value::Cons((_, ref t)) => t.clone(),
+		ref v => panic!("{} called with something not a stream {}", name, v.opaque()),
+	};
+	force_stream(tail)
+}
+
+pub fn fn_stream_head(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let s = force_stream(params.borrow().iter().next().unwrap());
+	let sref = s.borrow();
+	match *sref {
+		Value::Cons((ref h, _)) => h.clone(),
+		ref v => panic!("stream-head called with something not a stream {}", v.opaque()),
+	}
+}
+
+pub fn fn_stream_tail(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let s = params.borrow().iter().next().unwrap();
+	stream_tail(&s, "stream-tail")
+}
+
+pub fn fn_stream_take(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let n = match *it.next().unwrap().borrow() {
+		Value::Int(n) => n,
+		ref v => panic!("stream-take called with something not an integer {}", v.opaque()),
+	};
+	let mut s = force_stream(it.next().unwrap());
+	let mut builder = ListBuilder::new();
+	for _ in 0..n {
+		let head = match *s.borrow() {
+			Value::Nil => break,
+			Value::Cons((ref h, _)) => h.clone(),
+			ref v => panic!("stream-take called with something not a stream {}", v.opaque()),
+		};
+		builder.push(head);
+		s = stream_tail(&s, "stream-take");
+	}
+	lcell(builder.build())
+}
+
+fn stream_map(f: LCell<Value>, s: LCell<Value>, env: &LCell<Bindings>) -> LCell<Value> {
+	let s = force_stream(s);
+	let head = match *s.borrow() {
+		Value::Nil => return nil(),
+		Value::Cons((ref h, _)) => h.clone(),
+		ref v => panic!("stream-map called with something not a stream {}", v.opaque()),
+	};
+	let mapped = apply(f.clone(), cons(head, nil()), env.clone());
+	cons(mapped, call_promise(&STREAM_MAP_REST, vec![f, s], env))
+}
+
+fn fn_stream_map_rest(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let f = it.next().unwrap();
+	let s = it.next().unwrap();
+	stream_map(f, stream_tail(&s, "stream-map"), &env)
+}
+
+pub fn fn_stream_map(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let f = it.next().unwrap();
+	let s = it.next().unwrap();
+	stream_map(f, s, &env)
+}
+
+/// Skips ahead to the first element satisfying `pred`, without limit: on an
+/// infinite stream with no such element this does not return.
+fn stream_filter(pred: LCell<Value>, s: LCell<Value>, env: &LCell<Bindings>) -> LCell<Value> {
+	let mut s = force_stream(s);
+	loop {
+		let head = match *s.borrow() {
+			Value::Nil => return nil(),
+			Value::Cons((ref h, _)) => h.clone(),
+			ref v => panic!("stream-filter called with something not a stream {}", v.opaque()),
+		};
+		if apply(pred.clone(), cons(head.clone(), nil()), env.clone()).borrow().truthy() {
+			return cons(head, call_promise(&STREAM_FILTER_REST, vec![pred, s], env));
+		}
+		s = stream_tail(&s, "stream-filter");
+	}
+}
+
+fn fn_stream_filter_rest(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let pred = it.next().unwrap();
+	let s = it.next().unwrap();
+	stream_filter(pred, stream_tail(&s, "stream-filter"), &env)
+}
+
+pub fn fn_stream_filter(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let pred = it.next().unwrap();
+	let s = it.next().unwrap();
+	stream_filter(pred, s, &env)
+}
+
+/// `(iterate f x)` is the infinite stream x, (f x), (f (f x)), ...
+pub fn fn_iterate(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let f = it.next().unwrap();
+	let x = it.next().unwrap();
+	cons(x.clone(), call_promise(&ITERATE_REST, vec![f, x], &env))
+}
+
+fn fn_iterate_rest(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let f = it.next().unwrap();
+	let x = it.next().unwrap();
+	let next = apply(f.clone(), cons(x, nil()), env.clone());
+	fn_iterate(lcell(vec![f, next].into_iter().collect()), env)
+}
+
+static NATURALS_REST: Builtin = Builtin {
+	name: "naturals", fun: fn_naturals, min_args: 1, max_args: Some(1), arg_types: &[],
+	args: "", doc: "Computes the rest of a stream.",
+};
+
+/// `(naturals)` is the stream 0, 1, 2, ..., `(naturals n)` starts at n
+/// instead. It ends at the largest integer rather than overflow.
+pub fn fn_naturals(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let start = match params.borrow().iter().next() {
+		None => 0,
+		Some(v) => match *v.borrow() {
+			Value::Int(n) => n,
+			ref v => panic!("naturals called with something not an integer {}", v.opaque()),
+		},
+	};
+	let n = int(start);
+	let rest = match start.checked_add(1) {
+		Some(next) => call_promise(&NATURALS_REST, vec![int(next)], &lcell(make_root_bindings(&[], &[], vec![]))),
+		None => nil(),
+	};
+	cons(n, rest)
+}
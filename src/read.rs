@@ -19,35 +19,71 @@ pub fn read_list(inp: &str) -> Value {
 	parse(pairs)
 }
 
+/// What a line-oriented reader has so far.
+pub enum Input {
+	/// The terms read, which may be none.
+	Complete(Vec<LCell<Value>>),
+	/// Ends inside a list or a string, so the next line continues it.
+	Incomplete,
+	Invalid(String),
+}
+
+/// Whether parsing inp as rule fails only at its end, which is where more
+/// lines could still balance it.
+fn fails_at_end(rule: Rule, inp: &str) -> bool {
+	match L3Parser::parse_str(rule, inp) {
+		Ok(_) => true,
+		Err(pest::Error::ParsingError { ref pos, .. }) => pos.pos() == inp.len(),
+		Err(_) => false,
+	}
+}
+
+/// Whether more lines could make inp parse as rule: it fails to parse only
+/// at its end, or would parse with a string in it closed.
+fn incomplete(rule: Rule, inp: &str) -> bool {
+	fails_at_end(rule, inp) || fails_at_end(rule, &format!("{}\"", inp))
+}
+
+/// Reads any number of terms, as typed at the REPL.
+pub fn read_input(inp: &str) -> Input {
+	match L3Parser::parse_str(Rule::input, inp) {
+		Ok(pairs) => Input::Complete(parse_input(pairs)),
+		Err(_) if incomplete(Rule::input, inp) => Input::Incomplete,
+		Err(e) => Input::Invalid(e.to_string()),
+	}
+}
+
 use std::sync::Mutex;
 lazy_static!{
 	static ref INBUF: Mutex<String> = Mutex::new(String::new());
 }
 
+/// The length of the complete list at the start of inp, if there is one
+/// yet, with the whitespace after it.
+pub fn list_end(inp: &str) -> Option<usize> {
+	let pairs = L3Parser::parse_str(Rule::list, inp).ok()?;
+	let end = pairs.clone().next().map(|pair| pair.into_span().end());
+	end
+}
+
+/// Whether inp starts with a list, or could once more lines are added.
+pub fn list_pending(inp: &str) -> bool {
+	incomplete(Rule::list, inp)
+}
+
 pub fn read_stdin() -> LCell<Value> {
 	use std::io;
-	{
-		while !L3Parser::parse_str(Rule::list, &INBUF.lock().expect("STDIN BUFFER POISIONED!")).is_ok() {
-			let readlen = io::stdin().read_line(&mut INBUF.lock().expect("STDIN BUFFER POISIONED!"))
-				.expect("stdin read error");
-			if readlen == 0 {
-				return eof()
-			}
+	let mut inbuf = INBUF.lock().expect("STDIN BUFFER POISIONED!");
+	loop {
+		if let Some(end) = list_end(&inbuf) {
+			let remainder = inbuf.split_off(end);
+			let list = read_list(&inbuf);
+			*inbuf = remainder;
+			return lcell(list);
 		}
-	}
-	let good: String = { INBUF.lock().unwrap().clone() };
-	match L3Parser::parse_str(Rule::list, &good) {
-		Ok(pairs) => {
-			if let Some(pair) = pairs.clone().next() {
-				let len = pair.into_span().end();
-				let remainder = { INBUF.lock().unwrap().split_off(len) };
-				{ *INBUF.lock().unwrap() = remainder; }
-				lcell(parse(pairs))
-			} else {
-				panic!("read: something deeply wrong")
-			}
+		let readlen = io::stdin().read_line(&mut inbuf).expect("stdin read error");
+		if readlen == 0 {
+			return eof()
 		}
-		_ => nil(),
 	}
 }
-
@@ -0,0 +1,161 @@
+use super::*;
+
+#[derive(Debug, PartialEq, Trace, Finalize)]
+pub struct RecordType {
+	pub name: String,
+	pub fields: Vec<String>,
+}
+
+impl RecordType {
+	fn is(&self, other: &RecordType) -> bool {
+		self as *const RecordType == other as *const RecordType
+	}
+}
+
+#[derive(Debug, Clone, Trace, Finalize)]
+pub struct Record {
+	pub rtype: Gc<RecordType>,
+	pub fields: LCell<Vec<LCell<Value>>>,
+}
+
+impl Record {
+	/// The field cells, in the order the record type lists them.
+	pub fn field_cells(&self) -> Vec<LCell<Value>> {
+		self.fields.borrow().clone()
+	}
+}
+
+impl PartialEq for Record {
+	fn eq(&self, other: &Record) -> bool {
+		self.rtype.is(&other.rtype) && *self.fields.borrow() == *other.fields.borrow()
+	}
+}
+
+impl fmt::Display for Record {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write_shared(&Value::Record(self.clone()), f)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Trace, Finalize)]
+pub enum RecordOpKind {
+	Constructor,
+	Predicate,
+	Getter(usize),
+	Setter(usize),
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct RecordOp {
+	pub rtype: Gc<RecordType>,
+	pub kind: RecordOpKind,
+}
+
+impl PartialEq for RecordOp {
+	fn eq(&self, other: &RecordOp) -> bool {
+		self.rtype.is(&other.rtype) && self.kind == other.kind
+	}
+}
+
+impl RecordOp {
+	pub fn eval(&self, params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+		use self::RecordOpKind::*;
+		let mut it = params.borrow().iter();
+		match self.kind {
+			Constructor => {
+				let fields: Vec<LCell<Value>> = it.map(|f| lcell(f.borrow().clone())).collect();
+				if fields.len() != self.rtype.fields.len() {
+					panic!("{} expects {} fields, got {}", self, self.rtype.fields.len(), fields.len())
+				}
+				lcell(Value::Record(Record {
+					rtype: self.rtype.clone(),
+					fields: lcell(fields),
+				}))
+			},
+			Predicate => {
+				let v = it.next().unwrap_or_else(|| panic!("{} called without parameters", self));
+				let vref = v.borrow();
+				boolean(match *vref {
+					Value::Record(ref r) => r.rtype.is(&self.rtype),
+					_ => false,
+				})
+			},
+			Getter(idx) => {
+				let v = it.next().unwrap_or_else(|| panic!("{} called without parameters", self));
+				let vref = v.borrow();
+				let field = self.check(&*vref).fields.borrow()[idx].clone();
+				field
+			},
+			Setter(idx) => {
+				let v = it.next().unwrap_or_else(|| panic!("{} called without parameters", self));
+				let new_val = it.next().unwrap_or_else(|| panic!("{} called without a value", self));
+				let fields = self.check(&*v.borrow()).fields.clone();
+				if is_frozen(&fields) {
+					panic!("{} called on a frozen record {}", self, &*v.borrow());
+				}
+				fields.borrow_mut()[idx] = lcell(new_val.borrow().clone());
+				new_val
+			},
+		}
+	}
+
+	fn check<'a>(&self, v: &'a Value) -> &'a Record {
+		match *v {
+			Value::Record(ref r) if r.rtype.is(&self.rtype) => r,
+			ref v => panic!("{} called on something not a {}: {}", self, self.rtype.name, v.opaque()),
+		}
+	}
+}
+
+impl fmt::Display for RecordOp {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		use self::RecordOpKind::*;
+		let name = &self.rtype.name;
+		match self.kind {
+			Constructor => write!(f, "make-{}", name),
+			Predicate => write!(f, "{}?", name),
+			Getter(idx) => write!(f, "{}-{}", name, self.rtype.fields[idx]),
+			Setter(idx) => write!(f, "set-{}-{}!", name, self.rtype.fields[idx]),
+		}
+	}
+}
+
+pub fn eval_define_record(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let name = match *it.next().unwrap().borrow() {
+		Value::Ident(ref i) => i.clone(),
+		ref v => panic!("define-record name is not an identifier {}", v),
+	};
+	check_type_name(&name, "define-record");
+	let field_list = it.next().unwrap();
+	let fields: Vec<String> = field_list.borrow().iter()
+								.map(|field| {
+									match *field.borrow() {
+										Value::Ident(ref i) => i.clone(),
+										ref v => panic!("define-record field list containing something not an ident {}", v),
+									}
+								})
+								.collect();
+
+	let rtype = Gc::new(RecordType {
+		name: name,
+		fields: fields,
+	});
+
+	let mut kinds = vec![RecordOpKind::Constructor, RecordOpKind::Predicate];
+	for idx in 0..rtype.fields.len() {
+		kinds.push(RecordOpKind::Getter(idx));
+		kinds.push(RecordOpKind::Setter(idx));
+	}
+
+	let mut envref = env.borrow_mut();
+	for kind in kinds {
+		let op = RecordOp {
+			rtype: rtype.clone(),
+			kind: kind,
+		};
+		let binding = Value::Ident(op.to_string());
+		envref.set_binding(&binding, lcell(Value::Fn(Func::Record(op), true)));
+	}
+	nil()
+}
@@ -0,0 +1,366 @@
+use super::*;
+use rustyline::{Context, Editor, Helper};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter, MatchingBracketHighlighter};
+use rustyline::hint::{Hint, Hinter};
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::panic;
+use std::path::PathBuf;
+use std::time::Instant;
+
+const PROMPT: &'static str = "l3> ";
+const CONTINUE: &'static str = "... ";
+
+const COMMANDS: &'static [&'static str] = &[":load", ":env", ":time", ":help", ":quit"];
+
+const HELP: &'static str = "\
+:load FILE     runs FILE in the session
+:env [PREFIX]  shows what the session has bound, or the names starting with PREFIX
+:time EXPR     evaluates EXPR and shows how long it took
+:quit          ends the session";
+
+/// An interactive session: the environment it evaluates in and the part of
+/// an input typed so far.
+pub struct Repl {
+	env: LCell<Bindings>,
+	/// What was bound when the session started, to tell the bindings the
+	/// session made apart.
+	initial: BTreeMap<String, LCell<Value>>,
+	pending: String,
+}
+
+/// The message a panic was raised with.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+	if let Some(s) = payload.downcast_ref::<&str>() {
+		s.to_string()
+	} else if let Some(s) = payload.downcast_ref::<String>() {
+		s.clone()
+	} else {
+		"unknown error".to_string()
+	}
+}
+
+/// Runs f, returning None if it panics so the session can go on.
+fn recover<T, F: FnOnce() -> T>(f: F) -> Option<T> {
+	panic::catch_unwind(panic::AssertUnwindSafe(f)).ok()
+}
+
+impl Repl {
+	pub fn new(env: LCell<Bindings>) -> Repl {
+		let initial = env.borrow().own_bindings();
+		Repl {
+			env: env,
+			initial: initial,
+			pending: String::new(),
+		}
+	}
+
+	pub fn prompt(&self) -> &'static str {
+		if self.pending.is_empty() {
+			PROMPT
+		} else {
+			CONTINUE
+		}
+	}
+
+	/// Drops the part of an input typed so far.
+	pub fn cancel(&mut self) {
+		self.pending.clear();
+	}
+
+	/// Takes a line, evaluating and printing the terms it completes, or
+	/// running it as a command. Returns false once the session is over.
+	pub fn line(&mut self, line: &str) -> bool {
+		if self.pending.is_empty() && line.trim_start().starts_with(':') {
+			return self.command(line.trim());
+		}
+		self.pending.push_str(line);
+		self.pending.push('\n');
+		let terms = match recover(|| read_input(&self.pending)) {
+			Some(Input::Incomplete) => return true,
+			Some(Input::Invalid(e)) => {
+				eprintln!("{}", e);
+				self.pending.clear();
+				return true;
+			},
+			Some(Input::Complete(terms)) => terms,
+			None => {
+				self.pending.clear();
+				return true;
+			},
+		};
+		self.pending.clear();
+		self.eval_print(terms);
+		true
+	}
+
+	/// Prints the value of each term, stopping at the first error.
+	fn eval_print(&self, terms: Vec<LCell<Value>>) {
+		for term in terms {
+			match recover(|| eval(term, self.env.clone())) {
+				Some(v) => println!("{}", &*v.borrow()),
+				None => return,
+			}
+		}
+	}
+
+	fn command(&mut self, line: &str) -> bool {
+		let (name, arg) = match line.find(char::is_whitespace) {
+			Some(i) => (&line[..i], line[i..].trim()),
+			None => (line, ""),
+		};
+		match name {
+			":quit" | ":q" => return false,
+			":load" | ":l" if !arg.is_empty() => {
+				recover(|| run_file(arg, self.env.clone()));
+			},
+			":env" => self.print_env(arg),
+			":time" | ":t" => match recover(|| read_input(arg)) {
+				Some(Input::Complete(terms)) => {
+					let start = Instant::now();
+					self.eval_print(terms);
+					println!(";; {:?}", start.elapsed());
+				},
+				Some(Input::Incomplete) => eprintln!(":time needs a complete expression"),
+				Some(Input::Invalid(e)) => eprintln!("{}", e),
+				None => {},
+			},
+			":help" | ":h" => println!("{}", HELP),
+			_ => eprintln!("unknown command {}, :help lists the commands", line),
+		}
+		true
+	}
+
+	/// Prints the bindings made or changed since the session started.
+	fn print_env(&self, prefix: &str) {
+		for (name, v) in self.env.borrow().own_bindings() {
+			let unchanged = self.initial.get(&name).map_or(false, |old| same_cell(old, &v));
+			if !unchanged && name.starts_with(prefix) {
+				println!("{} = {}", name, &*v.borrow());
+			}
+		}
+	}
+}
+
+/// The innermost list left open in a line: how many terms it has so far,
+/// the identifier at its head, and the term being typed at the end.
+#[derive(Default)]
+struct OpenList {
+	terms: usize,
+	head: Option<String>,
+	typing: String,
+}
+
+impl OpenList {
+	fn start_term(&mut self) {
+		self.terms += 1;
+	}
+
+	fn end_term(&mut self) {
+		if self.terms == 1 && !self.typing.is_empty() {
+			self.head = Some(self.typing.clone());
+		}
+		self.typing.clear();
+	}
+}
+
+fn open_list(line: &str) -> Option<OpenList> {
+	let mut lists: Vec<OpenList> = Vec::new();
+	let mut chars = line.chars().peekable();
+	while let Some(c) = chars.next() {
+		if let Some(list) = lists.last_mut() {
+			if c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == ';' {
+				list.end_term();
+			}
+		}
+		match c {
+			'(' => {
+				if let Some(list) = lists.last_mut() {
+					list.start_term();
+				}
+				lists.push(OpenList::default());
+			},
+			')' => { lists.pop(); },
+			'"' => {
+				if let Some(list) = lists.last_mut() {
+					list.start_term();
+					list.typing.push('"');
+				}
+				while let Some(c) = chars.next() {
+					match c {
+						'"' => break,
+						'\\' => { chars.next(); },
+						_ => {},
+					}
+				}
+				if chars.peek().is_some() {
+					lists.last_mut().map(OpenList::end_term);
+				}
+			},
+			';' if chars.peek() == Some(&';') => {
+				while chars.peek().map_or(false, |c| *c != '\n') {
+					chars.next();
+				}
+			},
+			c if c.is_whitespace() => {},
+			c => if let Some(list) = lists.last_mut() {
+				if list.typing.is_empty() {
+					list.start_term();
+				}
+				list.typing.push(c);
+			},
+		}
+	}
+	lists.pop()
+}
+
+/// Shown after the cursor, but not inserted when the hint is accepted.
+pub struct ArgsHint(String);
+
+impl Hint for ArgsHint {
+	fn display(&self) -> &str {
+		&self.0
+	}
+
+	fn completion(&self) -> Option<&str> {
+		None
+	}
+}
+
+/// Completes the names bound in the session and the commands, hints at the
+/// arguments of the closure being called, and highlights the paren
+/// matching the one at the cursor.
+pub struct ReplHelper {
+	env: LCell<Bindings>,
+	brackets: MatchingBracketHighlighter,
+}
+
+impl ReplHelper {
+	pub fn new(env: LCell<Bindings>) -> ReplHelper {
+		ReplHelper {
+			env: env,
+			brackets: MatchingBracketHighlighter::new(),
+		}
+	}
+
+	/// Where the word before pos starts, and the names it could be
+	/// completed to.
+	pub fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+		let start = line[..pos].char_indices()
+			.rev()
+			.find(|&(_, c)| c.is_whitespace() || c == '(' || c == ')' || c == '"')
+			.map_or(0, |(i, c)| i + c.len_utf8());
+		let word = &line[start..pos];
+		let names = if start == 0 && word.starts_with(':') {
+			COMMANDS.iter().map(|c| c.to_string()).filter(|c| c.starts_with(word)).collect()
+		} else {
+			self.env.borrow().names().into_iter().filter(|n| n.starts_with(word)).collect()
+		};
+		(start, names)
+	}
+
+	/// The arguments still to be given to the closure called by the list
+	/// open at the end of line.
+	pub fn args_hint(&self, line: &str) -> Option<String> {
+		let list = open_list(line)?;
+		let (head, given, lead) = match list.head {
+			Some(ref head) if list.typing.is_empty() => (head.clone(), list.terms - 1, ""),
+			None if list.terms == 1 => (list.typing.clone(), 0, " "),
+			_ => return None,
+		};
+		let f = self.env.borrow().get_binding(&Value::Ident(head));
+		let f = f.borrow();
+		let args = match *f {
+			Value::Fn(Func::NFunc(ref def), _) => &def.args,
+			_ => return None,
+		};
+		if given >= args.len() {
+			return None;
+		}
+		Some(format!("{}{})", lead, args[given..].join(" ")))
+	}
+}
+
+impl Completer for ReplHelper {
+	type Candidate = Pair;
+
+	fn complete(&self, line: &str, pos: usize, _ctx: &Context) -> rustyline::Result<(usize, Vec<Pair>)> {
+		let (start, names) = self.completions(line, pos);
+		Ok((start, names.into_iter().map(|n| Pair { display: n.clone(), replacement: n }).collect()))
+	}
+}
+
+impl Hinter for ReplHelper {
+	type Hint = ArgsHint;
+
+	fn hint(&self, line: &str, pos: usize, _ctx: &Context) -> Option<ArgsHint> {
+		if pos < line.len() {
+			return None;
+		}
+		self.args_hint(line).map(ArgsHint)
+	}
+}
+
+impl Highlighter for ReplHelper {
+	fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+		self.brackets.highlight(line, pos)
+	}
+
+	fn highlight_char(&self, line: &str, pos: usize, kind: CmdKind) -> bool {
+		self.brackets.highlight_char(line, pos, kind)
+	}
+
+	fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+		Cow::Owned(format!("\x1b[2m{}\x1b[m", hint))
+	}
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+fn history_file() -> Option<PathBuf> {
+	std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".l3_history"))
+}
+
+/// Runs a session on the terminal until `:quit` or the end of input. Errors
+/// are reported and the session goes on; ^C drops a partly typed input.
+pub fn run_repl() {
+	let env = loaded_env();
+	let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()
+		.unwrap_or_else(|e| panic!("could not start the REPL: {}", e));
+	editor.set_helper(Some(ReplHelper::new(env.clone())));
+	let history = history_file();
+	if let Some(ref file) = history {
+		let _ = editor.load_history(file);
+	}
+	panic::set_hook(Box::new(|info| eprintln!("error: {}", panic_message(info.payload()))));
+
+	let mut repl = Repl::new(env);
+	loop {
+		match editor.readline(repl.prompt()) {
+			Ok(line) => {
+				if !line.trim().is_empty() {
+					let _ = editor.add_history_entry(line.as_str());
+				}
+				if !repl.line(&line) {
+					break;
+				}
+			},
+			Err(ReadlineError::Interrupted) => repl.cancel(),
+			Err(ReadlineError::Eof) => break,
+			Err(e) => panic!("REPL read error: {}", e),
+		}
+	}
+
+	if let Some(ref file) = history {
+		if let Err(e) = editor.save_history(file) {
+			eprintln!("could not save the history to {}: {}", file.display(), e);
+		}
+	}
+}
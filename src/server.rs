@@ -0,0 +1,227 @@
+use super::*;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+const OPS: &'static [&'static str] = &["eval", "interrupt", "complete", "describe"];
+
+/// What the threads of a server share about the request being evaluated.
+#[derive(Default)]
+struct Running {
+	/// The connection it came from, plus one, or 0 when there is none.
+	connection: AtomicUsize,
+	/// Interrupts evaluation on the thread answering requests.
+	interrupt: Arc<AtomicBool>,
+}
+
+/// What the connection threads tell the one evaluating requests. Values
+/// never cross threads, so requests are passed on as text.
+enum Event {
+	Connected(usize, Box<dyn Write + Send>),
+	Request(usize, String),
+	Closed(usize),
+}
+
+/// The op of a request, the first identifier in it.
+fn request_op(request: &str) -> Option<&str> {
+	request.split(|c: char| c == '(' || c == ')' || c.is_whitespace()).find(|s| !s.is_empty())
+}
+
+/// Passes on each request read from a connection, framed the way `read`
+/// frames its input: a request is complete once its list is balanced. A
+/// line that can't start a request is passed on by itself, to be answered
+/// as a bad request. Interrupts take effect here, since the evaluating
+/// thread is busy with the request to be interrupted.
+fn read_requests<S: Read>(id: usize, stream: S, running: Arc<Running>, events: Sender<Event>) {
+	let mut reader = BufReader::new(stream);
+	let mut buf = String::new();
+	loop {
+		let end = match list_end(&buf) {
+			Some(end) => Some(end),
+			None if !list_pending(&buf) => Some(buf.find('\n').map_or(buf.len(), |i| i + 1)),
+			None => None,
+		};
+		if let Some(end) = end {
+			let rest = buf.split_off(end);
+			let request = std::mem::replace(&mut buf, rest);
+			if request_op(&request) == Some("interrupt") && running.connection.load(Ordering::SeqCst) == id + 1 {
+				running.interrupt.store(true, Ordering::SeqCst);
+			}
+			if events.send(Event::Request(id, request)).is_err() {
+				return;
+			}
+			continue;
+		}
+		match reader.read_line(&mut buf) {
+			Ok(0) | Err(_) => break,
+			Ok(_) => {},
+		}
+	}
+	let _ = events.send(Event::Closed(id));
+}
+
+fn accept<S, I>(incoming: I, try_clone: fn(&S) -> io::Result<S>, running: Arc<Running>, events: Sender<Event>)
+	where S: Read + Write + Send + 'static, I: Iterator<Item = io::Result<S>>
+{
+	for (id, stream) in incoming.filter_map(Result::ok).enumerate() {
+		let writer = match try_clone(&stream) {
+			Ok(writer) => writer,
+			Err(_) => continue,
+		};
+		if events.send(Event::Connected(id, Box::new(writer))).is_err() {
+			return;
+		}
+		let events = events.clone();
+		let running = running.clone();
+		thread::spawn(move || read_requests(id, stream, running, events));
+	}
+}
+
+fn str_value(s: &str) -> LCell<Value> {
+	lcell(Value::Str(s.to_string()))
+}
+
+struct Session {
+	out: Box<dyn Write + Send>,
+	env: LCell<Bindings>,
+}
+
+impl Session {
+	fn send(&mut self, parts: Vec<LCell<Value>>) {
+		let message: Value = parts.into_iter().collect();
+		// A connection that went away is noticed by the thread reading it.
+		let _ = writeln!(self.out, "{}", message);
+		let _ = self.out.flush();
+	}
+
+	/// Evaluates each term of code, sending what it prints and its value,
+	/// or the error that stops it.
+	fn eval(&mut self, id: usize, rid: &LCell<Value>, code: &str, running: &Running) {
+		let terms = match panic::catch_unwind(|| read_input(code)) {
+			Ok(Input::Complete(terms)) => terms,
+			Ok(Input::Incomplete) => return self.send(vec![ident("error"), rid.clone(), str_value("incomplete input")]),
+			Ok(Input::Invalid(e)) => return self.send(vec![ident("error"), rid.clone(), str_value(&e)]),
+			Err(e) => return self.send(vec![ident("error"), rid.clone(), str_value(&panic_message(&*e))]),
+		};
+		for term in terms {
+			let env = self.env.clone();
+			capture_output();
+			running.interrupt.store(false, Ordering::SeqCst);
+			running.connection.store(id + 1, Ordering::SeqCst);
+			let result = panic::catch_unwind(panic::AssertUnwindSafe(|| eval(term, env)));
+			running.connection.store(0, Ordering::SeqCst);
+			let out = captured_output();
+			if !out.is_empty() {
+				self.send(vec![ident("out"), rid.clone(), str_value(&out)]);
+			}
+			match result {
+				Ok(v) => self.send(vec![ident("value"), rid.clone(), str_value(&v.borrow().to_string())]),
+				Err(e) => return self.send(vec![ident("error"), rid.clone(), str_value(&panic_message(&*e))]),
+			}
+		}
+	}
+
+	/// Answers what can't be read as a request, which has no id to answer
+	/// with.
+	fn reject(&mut self, message: &str) {
+		self.send(vec![ident("error"), ident("nil"), str_value(message)]);
+		self.send(vec![ident("done"), ident("nil")]);
+	}
+
+	/// Answers a request `(op id arg...)`. Every request is answered with
+	/// some messages `(kind id data...)` and then `(done id)`.
+	fn respond(&mut self, id: usize, request: &str, running: &Running) {
+		if list_end(request).is_none() {
+			return self.reject(&format!("bad request {}", request.trim()));
+		}
+		let parts: Vec<LCell<Value>> = match panic::catch_unwind(|| read_list(request)) {
+			Ok(list) => list.iter().collect(),
+			Err(e) => return self.reject(&panic_message(&*e)),
+		};
+		let rid = parts.get(1).cloned().unwrap_or_else(nil);
+		let op = parts.get(0).map_or(String::new(), |op| op.borrow().to_string());
+		let arg = parts.get(2).and_then(|a| match *a.borrow() {
+			Value::Str(ref s) => Some(s.clone()),
+			_ => None,
+		});
+		match (op.as_str(), arg) {
+			("eval", Some(code)) => self.eval(id, &rid, &code, running),
+			// The interrupt itself was done by the thread reading the request.
+			("interrupt", _) => {},
+			("complete", Some(prefix)) => {
+				let names: Value = self.env.borrow().names().into_iter()
+					.filter(|n| n.starts_with(&prefix))
+					.map(|n| str_value(&n))
+					.collect();
+				self.send(vec![ident("completions"), rid.clone(), lcell(names)]);
+			},
+			("describe", _) => {
+				let ops: Value = OPS.iter().map(ident).collect();
+				self.send(vec![ident("ops"), rid.clone(), lcell(ops), ident("version"), str_value(crate_version!())]);
+			},
+			_ => self.send(vec![ident("error"), rid.clone(), str_value(&format!("bad request {}", request.trim()))]),
+		}
+		self.send(vec![ident("done"), rid]);
+	}
+}
+
+/// Answers requests one at a time, all on this thread, so that sessions
+/// can share one environment.
+fn run_sessions(events: Receiver<Event>, isolated: bool, running: Arc<Running>) {
+	set_interrupt_flag(running.interrupt.clone());
+	let shared = loaded_env();
+	let mut sessions = HashMap::new();
+	for event in events {
+		match event {
+			Event::Connected(id, out) => {
+				let env = if isolated { loaded_env() } else { shared.clone() };
+				sessions.insert(id, Session { out: out, env: env });
+			},
+			Event::Request(id, request) => if let Some(session) = sessions.get_mut(&id) {
+				session.respond(id, &request, &running);
+			},
+			Event::Closed(id) => { sessions.remove(&id); },
+		}
+	}
+}
+
+/// Serves REPL sessions for editors on addr, either `host:port` or the
+/// path of a Unix domain socket. Each connection gets a session in the
+/// environment all connections share, or with isolated in one of its own.
+///
+/// Requests and responses are lists, one per line: `(eval id "code")`,
+/// `(interrupt id)` to stop the eval going on in the same connection,
+/// `(complete id "prefix")` and `(describe id)`. Responses carry the id of
+/// their request: `(out id "text")`, `(value id "printed")`,
+/// `(error id "message")`, `(completions id ("name"...))`,
+/// `(ops id (op...) version "v")`, and last `(done id)`. A line that is
+/// not a request is answered with `(error nil "message")` and `(done nil)`.
+pub fn serve(addr: &str, isolated: bool) {
+	let (events, requests) = channel();
+	let running = Arc::new(Running::default());
+	let accepting = running.clone();
+	match addr.parse::<SocketAddr>() {
+		Ok(sock) => {
+			let listener = TcpListener::bind(sock).unwrap_or_else(|e| panic!("could not listen on {}: {}", addr, e));
+			println!("listening on {}", listener.local_addr().unwrap());
+			thread::spawn(move || accept(listener.incoming(), TcpStream::try_clone, accepting, events));
+		},
+		Err(_) => {
+			// A socket left behind by a server that is gone would make bind fail.
+			if std::fs::metadata(addr).map_or(false, |m| m.file_type().is_socket()) {
+				let _ = std::fs::remove_file(addr);
+			}
+			let listener = UnixListener::bind(addr).unwrap_or_else(|e| panic!("could not listen on {}: {}", addr, e));
+			println!("listening on {}", addr);
+			thread::spawn(move || accept(listener.incoming(), UnixStream::try_clone, accepting, events));
+		},
+	}
+	run_sessions(requests, isolated, running);
+}
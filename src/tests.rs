@@ -121,7 +121,6 @@ fn read_bench(b: &mut Bencher) {
 }
 
 #[test]
-#[ignore]
 fn stack_overflow() {
 	let program = "(filter (fn (n) (mod n 7)) (for n (seq 10000) n))";
 	let parsed = lcell(read_program(program));
@@ -138,3 +137,324 @@ fn eval_bench(b: &mut Bencher) {
 		run_program(parsed.clone(), env.clone());
 	})
 }
+
+#[test]
+fn equal_cyclic() {
+	let a = lcell(read_list("(1 2)"));
+	let b = lcell(read_list("(1 2)"));
+	*a.borrow().tail().borrow().tail().borrow_mut() = a.borrow().clone();
+	*b.borrow().tail().borrow().tail().borrow_mut() = b.borrow().clone();
+	assert!(is_equal(&a, &b));
+	*b.borrow().head().borrow_mut() = Value::Int(3);
+	assert!(!is_equal(&a, &b));
+}
+
+#[test]
+fn closure_equality_ignores_env() {
+	let env = loaded_env();
+	let f = eval(lcell(read_list("(fn (x) x)")), env.clone());
+	let g = eval(lcell(read_list("(fn (x) x)")), env.clone());
+	assert!(is_eqv(&f, &f));
+	assert!(!is_equal(&f, &g));
+}
+
+#[test]
+fn print_read_cyclic() {
+	let l = read_list("(1 2 3)");
+	let last = l.tail().borrow().tail();
+	*last.borrow().tail().borrow_mut() = l.clone();
+	let printed = format!("{}", l);
+	assert_eq!(printed, "#0=(1 2 3 . #0#)");
+	let read = read_list(&printed);
+	assert_eq!(format!("{}", read), printed);
+	let fourth = read.tail().borrow().tail().borrow().tail();
+	assert!(is_eq(&fourth, &lcell(read.clone())));
+}
+
+#[test]
+fn print_read_shared() {
+	let printed = "((#0=(1) #1=(2)) #0# #1# #0#)";
+	assert_eq!(format!("{}", read_list(printed)), printed);
+}
+
+fn long_list(n: i32) -> Value {
+	let mut builder = ListBuilder::new();
+	for i in 0..n {
+		builder.push(int(i));
+	}
+	builder.build()
+}
+
+#[test]
+fn million_element_lists() {
+	let a = long_list(1000000);
+	let printed = format!("{}", a);
+	assert!(printed.starts_with("(0 1 2 "));
+	assert!(printed.ends_with(" 999998 999999)"));
+	assert!(format!("{:?}", a).ends_with("Int(999999)]"));
+	let copy = a.deep_copy();
+	assert_eq!(copy, a);
+	*copy.head().borrow_mut() = Value::Int(-1);
+	assert!(copy != a);
+	drop(a);
+	gc::force_collect();
+	assert_eq!(format!("{}", copy).len(), printed.len() + 1);
+}
+
+#[test]
+fn deep_copy_keeps_cycles() {
+	let l = read_list("(#0=(1) #0# . #1=(2 . #1#))");
+	let copy = l.deep_copy();
+	assert_eq!(format!("{}", copy), format!("{}", l));
+	*copy.head().borrow().head().borrow_mut() = Value::Int(3);
+	assert_eq!(format!("{}", copy), "(#0=(3) #0# . #1=(2 . #1#))");
+	assert_eq!(format!("{}", l), "(#0=(1) #0# . #1=(2 . #1#))");
+}
+
+#[test]
+fn freeze_leaves_shared_constants() {
+	let l = lcell(Value::Cons((boolean(true), lcell(Value::Cons((nil(), nil()))))));
+	freeze(&l);
+	assert!(is_frozen(&l));
+	assert!(!is_frozen(&boolean(true)));
+	assert!(!is_frozen(&nil()));
+}
+
+#[test]
+fn parameterize_restores_on_panic() {
+	let env = loaded_env();
+	run_program(lcell(read_program("(set level (make-parameter 1))")), env.clone());
+	let failing = lcell(read_program("(parameterize ((level 2)) (head 5))"));
+	let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+		run_program(failing, env.clone());
+	}));
+	assert!(result.is_err());
+	assert_eq!(eval(lcell(read_list("(level)")), env), int(1));
+}
+
+#[test]
+fn closure_print_reads_back() {
+	let env = loaded_env();
+	let exp = "(fn (x y) \"Adds.\" (+ x (quote (1 \"a\"))))";
+	let fun = eval(lcell(read_list(exp)), env);
+	let printed = format!("{}", &*fun.borrow());
+	assert_eq!(printed, exp);
+	assert_eq!(read_list(&printed), read_list(exp));
+}
+
+#[test]
+fn errors_name_closures() {
+	let env = loaded_env();
+	run_program(lcell(read_program("(fn f (x) (+ x 1))")), env.clone());
+	let failing = lcell(read_program("(+ 1 f)"));
+	let error = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+		run_program(failing, env.clone());
+	})).unwrap_err();
+	assert_eq!(panic_message(&*error), "+ expects argument 2 to be int, got #<closure f>");
+}
+
+#[test]
+fn import_searches_l3_path() {
+	std::env::set_var("L3_PATH", concat!(env!("CARGO_MANIFEST_DIR"), "/tests/modules"));
+	let env = loaded_env();
+	run_program(lcell(read_program("(import \"geometry.l3\" (prefix g/))")), env.clone());
+	assert_eq!(eval(lcell(read_list("(g/area 3)")), env), int(27));
+}
+
+fn write_file(path: &std::path::Path, contents: &str) {
+	std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+	std::fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn project_run_and_lock() {
+	let root = std::env::temp_dir().join(format!("l3-project-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&root);
+	write_file(&root.join("shapes/l3.toml"), "[project]\nname = \"shapes\"\nentry = \"lib/shapes.l3\"\nsources = [\"lib\"]\n");
+	write_file(&root.join("shapes/lib/shapes.l3"), "(fn square (x) (* x x))\n");
+	write_file(&root.join("app/l3.toml"), "[project]\nname = \"app\"\nentry = \"src/main.l3\"\n\n[dependencies]\nshapes = { path = \"../shapes\" }\n");
+	write_file(&root.join("app/src/main.l3"), "(import \"shapes.l3\")\n(set result (square 7))\n");
+
+	let env = run_project(&root.join("app/src"));
+	assert_eq!(env.borrow().get_binding(&Value::Ident("result".to_string())), int(49));
+	let lock = std::fs::read_to_string(root.join("app/l3.lock")).unwrap();
+	assert!(lock.contains("path = \"../shapes\""));
+	assert!(lock.contains(&sha256_hex(b"(fn square (x) (* x x))\n")));
+
+	write_file(&root.join("shapes/lib/shapes.l3"), "(fn square (x) (* x x x))\n");
+	let stale = std::panic::catch_unwind(|| Project::load(&root.join("app")).check_lock());
+	assert!(stale.is_err());
+	Project::load(&root.join("app")).write_lock();
+	Project::load(&root.join("app")).check_lock();
+	std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn manifest_defaults_and_errors() {
+	let root = std::env::temp_dir().join(format!("l3-manifest-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&root);
+	write_file(&root.join("l3.toml"), "[project]\nname = \"app\"\nentry = \"main.l3\"\n\n[dependencies.shapes]\npath = '../shapes'\n");
+	let manifest = read_manifest(&root);
+	assert_eq!(manifest.sources, vec![std::path::PathBuf::from("src")]);
+	assert_eq!(manifest.dependencies, vec![("shapes".to_string(), std::path::PathBuf::from("../shapes"))]);
+	for bad in &[
+		"[project]\nname = \"app\"\n",
+		"[project]\nname = 1\nentry = \"main.l3\"\n",
+		"[project]\nname = \"app\"\nentry = \"main.l3\"\nsources = \"src\"\n",
+		"[project]\nname = \"app\" entry = \"main.l3\"\n",
+	] {
+		write_file(&root.join("l3.toml"), bad);
+		assert!(std::panic::catch_unwind(|| read_manifest(&root)).is_err(), "{}", bad);
+	}
+	std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn lock_reads_back_quoted_names() {
+	let root = std::env::temp_dir().join(format!("l3-lock-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&root);
+	write_file(&root.join("odd/l3.toml"), "[project]\nname = \"odd \\\"one\\\"\"\nentry = \"src/a.l3\"\n");
+	write_file(&root.join("odd/src/a.l3"), "1\n");
+	write_file(&root.join("odd/src/it's \"b\".l3"), "2\n");
+	write_file(&root.join("app/l3.toml"), "[project]\nname = \"app\"\nentry = \"src/main.l3\"\n\n[dependencies]\n\"odd \\\"one\\\"\" = { path = \"../odd\" }\n");
+
+	Project::load(&root.join("app")).check_lock();
+	let lock = std::fs::read_to_string(root.join("app/l3.lock")).unwrap();
+	assert!(lock.starts_with("# Written by"));
+	Project::load(&root.join("app")).check_lock();
+
+	std::fs::remove_file(root.join("odd/src/a.l3")).unwrap();
+	let removed = std::panic::catch_unwind(|| Project::load(&root.join("app")).check_lock()).unwrap_err();
+	let message = removed.downcast_ref::<String>().unwrap();
+	assert!(message.contains("src/a.l3 of odd \"one\" was removed"), "{}", message);
+	std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn read_input_continues_unbalanced() {
+	match read_input("x (1 2) \"s\"") {
+		Input::Complete(terms) => assert_eq!(terms.len(), 3),
+		_ => panic!("expected complete input"),
+	}
+	for partial in &["(1 (2", "(print \"ab", "(1\n  ;; (\n"] {
+		assert!(match read_input(partial) { Input::Incomplete => true, _ => false }, "{}", partial);
+	}
+	for invalid in &[")", "(1))"] {
+		assert!(match read_input(invalid) { Input::Invalid(_) => true, _ => false }, "{}", invalid);
+	}
+}
+
+#[test]
+fn repl_session_survives_errors() {
+	let env = loaded_env();
+	let mut repl = Repl::new(env.clone());
+	assert!(repl.line("(set x"));
+	assert_eq!(repl.prompt(), "... ");
+	assert!(repl.line("  (+ 1 2))"));
+	assert_eq!(repl.prompt(), "l3> ");
+	assert!(repl.line("(head 5)"));
+	assert!(repl.line("(#1#)"));
+	assert!(repl.line("(set y (* x 2))"));
+	assert!(!repl.line(":quit"));
+	assert_eq!(env.borrow().get_binding(&Value::Ident("y".to_string())), int(6));
+}
+
+#[test]
+fn repl_survives_runaway_recursion() {
+	std::thread::Builder::new().stack_size(EVAL_STACK_SIZE).spawn(|| {
+		let env = loaded_env();
+		let mut repl = Repl::new(env.clone());
+		assert!(repl.line("(fn down (n) (+ 1 (down n)))"));
+		assert!(repl.line("(down 1)"));
+		// the nesting counted before the error is given back
+		assert!(repl.line("(fn count (n) (if (= n 0) 0 (+ 1 (count (- n 1)))))"));
+		assert!(repl.line("(set y (count 3000))"));
+		assert_eq!(env.borrow().get_binding(&Value::Ident("y".to_string())), int(3000));
+	}).unwrap().join().unwrap();
+}
+
+#[test]
+fn repl_completes_and_hints() {
+	let env = loaded_env();
+	run_program(lcell(read_program("(fn area (width height) (* width height))")), env.clone());
+	let helper = ReplHelper::new(lcell(make_empty_bindings(env)));
+	let (start, names) = helper.completions("(print (are", 11);
+	assert_eq!((start, names), (8, vec!["area".to_string()]));
+	assert!(helper.completions("(str", 4).1.contains(&"string?".to_string()));
+	assert_eq!(helper.completions(":q", 2).1, vec![":quit".to_string()]);
+	assert_eq!(helper.completions("(print\u{3000}are", 12), (9, vec!["area".to_string()]));
+	assert_eq!(helper.args_hint("(area").as_ref().map(String::as_str), Some(" width height)"));
+	assert_eq!(helper.args_hint("(print (area 2 ").as_ref().map(String::as_str), Some("height)"));
+	assert_eq!(helper.args_hint("(area \"a b\" (f 1) ").as_ref().map(String::as_str), None);
+	assert_eq!(helper.args_hint("(area (f 1) ").as_ref().map(String::as_str), Some("height)"));
+	assert_eq!(helper.args_hint("(area 2"), None);
+	assert_eq!(helper.args_hint("(+ 1 "), None);
+}
+
+fn server_exchange(conn: &mut std::io::BufReader<std::os::unix::net::UnixStream>, request: &str, id: &str) -> Vec<String> {
+	use std::io::{BufRead, Write};
+	writeln!(conn.get_mut(), "{}", request).unwrap();
+	let mut replies = Vec::new();
+	loop {
+		let mut line = String::new();
+		conn.read_line(&mut line).unwrap();
+		let line = line.trim_end().to_string();
+		if line == format!("(done {})", id) {
+			return replies;
+		}
+		replies.push(line);
+	}
+}
+
+#[test]
+fn server_sessions() {
+	use std::io::Write;
+	use std::os::unix::net::UnixStream;
+	let path = std::env::temp_dir().join(format!("l3-server-{}.sock", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	// Left behind as if by a server that is gone.
+	drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+	let addr = path.to_str().unwrap().to_string();
+	std::thread::Builder::new().stack_size(EVAL_STACK_SIZE).spawn(move || serve(&addr, false)).unwrap();
+	let stream = (0..100)
+		.filter_map(|_| UnixStream::connect(&path).map_err(|_| std::thread::sleep(std::time::Duration::from_millis(20))).ok())
+		.next()
+		.expect("server did not start");
+	let mut conn = std::io::BufReader::new(stream);
+
+	assert_eq!(server_exchange(&mut conn, "(eval 1 \"(print \\\"hi\\\") (+ 2 3)\")", "1"),
+		vec!["(out 1 \"hi\\n\")", "(value 1 \"true\")", "(value 1 \"5\")"]);
+	assert_eq!(server_exchange(&mut conn, "(eval 2 \"(head 5)\")", "2"),
+		vec!["(error 2 \"head expects argument 1 to be pair, got 5\")"]);
+	assert_eq!(server_exchange(&mut conn, "(complete 3 \"stream-t\")", "3"),
+		vec!["(completions 3 (\"stream-tail\" \"stream-take\"))"]);
+
+	writeln!(conn.get_mut(), "(eval 4 \"(while true 1)\")").unwrap();
+	std::thread::sleep(std::time::Duration::from_millis(200));
+	assert_eq!(server_exchange(&mut conn, "(interrupt 5)", "5"),
+		vec!["(error 4 \"interrupted\")", "(done 4)"]);
+
+	writeln!(conn.get_mut(), "(eval 6 \"(loop)\")").unwrap();
+	std::thread::sleep(std::time::Duration::from_millis(200));
+	assert_eq!(server_exchange(&mut conn, "(interrupt 7)", "7"),
+		vec!["(error 6 \"interrupted\")", "(done 6)"]);
+
+	let replies = server_exchange(&mut conn, "(eval 8 \"(fn down (n) (+ 1 (down n))) (down 1)\")", "8");
+	assert_eq!(replies.last().unwrap(), "(error 8 \"evaluation nested more than 10000 deep\")");
+
+	assert_eq!(server_exchange(&mut conn, "hello", "nil"),
+		vec!["(error nil \"bad request hello\")"]);
+	assert_eq!(server_exchange(&mut conn, "(eval 9 \"(+ 1 2)\")", "9"),
+		vec!["(value 9 \"3\")"]);
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[bench]
+fn stream_bench(b: &mut Bencher) {
+	let program = "(stream-take 100 (stream-filter (fn (n) (mod n 7)) (naturals)))";
+	let parsed = lcell(read_program(program));
+	let env = loaded_env();
+	b.iter(|| {
+		run_program(parsed.clone(), env.clone());
+	})
+}
@@ -1,8 +1,9 @@
 use super::*;
+use std::collections::HashMap;
 
 pub type LCell<T> = Gc<GcCell<T>>;
 
-#[derive(Debug, PartialEq, Clone, PartialOrd, Trace, Finalize)]
+#[derive(Clone)]
 pub enum Value {
 	Nil,
 	Cons((LCell<Value>, LCell<Value>)),
@@ -10,10 +11,161 @@ pub enum Value {
 	True,
 	Int(i32),
 	Ident(String),
+	Str(String),
 	Fn(Func, bool),
+	Record(Record),
+	Class(Gc<Class>),
+	Object(Object),
+	Promise(LCell<Promise>),
+	Coroutine(Gc<Coroutine>),
+	Module(Gc<Module>),
 	EOF,
 }
 
+thread_local! {
+	static TRACE_QUEUE: RefCell<Option<Vec<*const LCell<Value>>>> = RefCell::new(None);
+}
+
+/// Marks a cell reachable from a pair. The collector marks recursively, so
+/// following a long list would use one stack frame per element. Instead
+/// the outermost call drains a queue that nested calls push onto.
+unsafe fn trace_pair_cell(cell: &LCell<Value>) {
+	let queued = TRACE_QUEUE.with(|q| match *q.borrow_mut() {
+		Some(ref mut queue) => {
+			queue.push(cell as *const LCell<Value>);
+			true
+		},
+		None => false,
+	});
+	if queued {
+		return;
+	}
+	TRACE_QUEUE.with(|q| *q.borrow_mut() = Some(Vec::new()));
+	cell.trace();
+	while let Some(next) = TRACE_QUEUE.with(|q| q.borrow_mut().as_mut().unwrap().pop()) {
+		(*next).trace();
+	}
+	TRACE_QUEUE.with(|q| *q.borrow_mut() = None);
+}
+
+macro_rules! value_fields {
+	($v:expr, $mark:ident, $mark_pair:ident) => (
+		match *$v {
+			Value::Cons((ref h, ref t)) => {
+				$mark_pair(h);
+				$mark_pair(t);
+			},
+			Value::Fn(ref fun, _) => $mark(fun),
+			Value::Record(ref r) => $mark(r),
+			Value::Class(ref c) => $mark(c),
+			Value::Object(ref o) => $mark(o),
+			Value::Promise(ref p) => $mark(p),
+			Value::Coroutine(ref c) => $mark(c),
+			Value::Module(ref m) => $mark(m),
+			Value::Nil | Value::False | Value::True | Value::Int(_) | Value::Ident(_) | Value::Str(_) | Value::EOF => {},
+		}
+	)
+}
+
+impl Finalize for Value {
+	fn finalize(&self) {
+		forget_frozen(self);
+	}
+}
+
+unsafe impl Trace for Value {
+	unsafe fn trace(&self) {
+		unsafe fn mark<T: Trace>(it: &T) {
+			it.trace();
+		}
+		value_fields!(self, mark, trace_pair_cell)
+	}
+
+	unsafe fn root(&self) {
+		unsafe fn mark<T: Trace>(it: &T) {
+			it.root();
+		}
+		value_fields!(self, mark, mark)
+	}
+
+	unsafe fn unroot(&self) {
+		unsafe fn mark<T: Trace>(it: &T) {
+			it.unroot();
+		}
+		value_fields!(self, mark, mark)
+	}
+
+	fn finalize_glue(&self) {
+		fn mark<T: Trace>(it: &T) {
+			it.finalize_glue();
+		}
+		Finalize::finalize(self);
+		value_fields!(self, mark, mark)
+	}
+}
+
+/// Structural equality, see `equal_values`.
+impl PartialEq for Value {
+	fn eq(&self, other: &Value) -> bool {
+		equal_values(self, other)
+	}
+}
+
+impl fmt::Debug for Value {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Value::Nil => write!(f, "Nil"),
+			Value::Cons((ref h, ref t)) => {
+				write!(f, "Cons[{:?}", &*h.borrow())?;
+				let mut pos = t.clone();
+				loop {
+					let next = match *pos.borrow() {
+						Value::Nil => break,
+						Value::Cons((ref h, ref t)) => {
+							write!(f, ", {:?}", &*h.borrow())?;
+							t.clone()
+						},
+						ref v => {
+							write!(f, " . {:?}", v)?;
+							break;
+						},
+					};
+					pos = next;
+				}
+				write!(f, "]")
+			},
+			Value::False => write!(f, "False"),
+			Value::True => write!(f, "True"),
+			Value::Int(i) => write!(f, "Int({})", i),
+			Value::Ident(ref i) => write!(f, "Ident({:?})", i),
+			Value::Str(ref s) => write!(f, "Str({:?})", s),
+			Value::Fn(ref fun, ev) => write!(f, "Fn({:?}, {})", fun, ev),
+			Value::Record(ref r) => write!(f, "Record({:?})", r),
+			Value::Class(ref c) => write!(f, "Class({:?})", c),
+			Value::Object(ref o) => write!(f, "Object({:?})", o),
+			Value::Promise(_) => write!(f, "Promise"),
+			Value::Coroutine(ref c) => write!(f, "{:?}", &**c),
+			Value::Module(ref m) => write!(f, "{:?}", &**m),
+			Value::EOF => write!(f, "EOF"),
+		}
+	}
+}
+
+/// The type names of the built-in values, which record types and classes
+/// can't be named, or the type predicates would take their instances for
+/// built-in values.
+const BUILTIN_TYPES: &'static [&'static str] = &[
+	"nil", "list", "pair", "bool", "int", "symbol", "string", "fn", "special", "class",
+	"promise", "coroutine", "module", "eof", "record", "object", "any",
+];
+
+/// Panics if name is the type name of a built-in value.
+pub fn check_type_name(name: &str, what: &str) {
+	if BUILTIN_TYPES.contains(&name) {
+		panic!("{} cannot be named {}, which is a built-in type", what, name);
+	}
+}
+
 impl Value {
 	pub fn head(&self) -> LCell<Value> {
 		match *self {
@@ -42,6 +194,80 @@ impl Value {
 		}
 	}
 
+	/// The types of the values whose types don't depend on a record type or
+	/// class, most specific first.
+	fn fixed_types(&self) -> Option<&'static [&'static str]> {
+		Some(match *self {
+			Value::Nil => &["nil", "list"],
+			Value::Cons(_) => &["pair", "list"],
+			Value::False | Value::True => &["bool"],
+			Value::Int(_) => &["int"],
+			Value::Ident(_) => &["symbol"],
+			Value::Str(_) => &["string"],
+			Value::Fn(_, true) => &["fn"],
+			Value::Fn(_, false) => &["special"],
+			Value::Class(_) => &["class"],
+			Value::Promise(_) => &["promise"],
+			Value::Coroutine(_) => &["coroutine"],
+			Value::Module(_) => &["module"],
+			Value::EOF => &["eof"],
+			Value::Record(_) | Value::Object(_) => return None,
+		})
+	}
+
+	/// Type names this value belongs to, most specific first, always ending
+	/// in `any`. Shared by the type predicates and generic function dispatch.
+	pub fn type_precedence(&self) -> Vec<String> {
+		let mut types: Vec<String> = match *self {
+			Value::Record(ref r) => vec![r.rtype.name.clone(), "record".to_string()],
+			Value::Object(ref o) => {
+				let mut chain = Vec::new();
+				let mut class = Some(o.class.clone());
+				while let Some(c) = class {
+					chain.push(c.name.clone());
+					class = c.superclass.clone();
+				}
+				chain.push("object".to_string());
+				chain
+			},
+			ref v => v.fixed_types().unwrap().iter().map(|t| t.to_string()).collect(),
+		};
+		types.push("any".to_string());
+		types
+	}
+
+	/// The value as error messages show it, with a closure named rather
+	/// than printed with its code.
+	pub fn opaque(&self) -> String {
+		match *self {
+			Value::Fn(Func::NFunc(ref def), _) => def.opaque(),
+			ref v => v.to_string(),
+		}
+	}
+
+	pub fn has_type(&self, name: &str) -> bool {
+		match self.fixed_types() {
+			Some(types) => name == "any" || types.contains(&name),
+			None => self.type_precedence().iter().any(|t| t == name),
+		}
+	}
+
+	/// Copies the list structure of the value into fresh cells, keeping
+	/// shared and cyclic structure intact. Works through an explicit queue
+	/// of pairs still to copy, so long lists don't grow the Rust stack.
+	pub fn deep_copy(&self) -> Value {
+		let mut copies = PairCopies::new();
+		let mut work = Vec::new();
+		let root = copy_shallow(self, &mut copies, &mut work);
+		while let Some((old, new)) = work.pop() {
+			let head = copy_shallow(&*old.0.borrow(), &mut copies, &mut work);
+			let tail = copy_shallow(&*old.1.borrow(), &mut copies, &mut work);
+			*new.0.borrow_mut() = head;
+			*new.1.borrow_mut() = tail;
+		}
+		root
+	}
+
 	pub fn truthy(&self) -> bool {
 		match self {
 			&Value::Nil | &Value::False => false,
@@ -50,6 +276,31 @@ impl Value {
 	}
 }
 
+type PairCopies = HashMap<(usize, usize), (LCell<Value>, LCell<Value>)>;
+type PairWork = Vec<((LCell<Value>, LCell<Value>), (LCell<Value>, LCell<Value>))>;
+
+/// Copies one level of `v` for `Value::deep_copy`. A pair seen for the first
+/// time gets a fresh pair of empty cells, queued to be filled in later.
+fn copy_shallow(v: &Value, copies: &mut PairCopies, work: &mut PairWork) -> Value {
+	match *v {
+		Value::Cons(ref pair) => {
+			let key = pair_key(pair);
+			if let Some(copy) = copies.get(&key) {
+				return Value::Cons(copy.clone());
+			}
+			let copy = (lcell(Value::Nil), lcell(Value::Nil));
+			copies.insert(key, copy.clone());
+			work.push((pair.clone(), copy.clone()));
+			Value::Cons(copy)
+		},
+		Value::Record(ref r) => Value::Record(Record {
+			rtype: r.rtype.clone(),
+			fields: lcell(r.field_cells().iter().map(|f| lcell(copy_shallow(&*f.borrow(), copies, work))).collect()),
+		}),
+		ref v => v.clone(),
+	}
+}
+
 #[derive(Clone)]
 pub struct ListIterator {
 	pos: LCell<Value>,
@@ -69,13 +320,28 @@ impl Iterator for ListIterator {
 				retv = Some(h.clone());
 				t.clone()
 			},
-			v => panic!("ListIterator got something strange {:?}", v)
+			v => panic!("expected a proper list, found the improper tail {}", v)
 		};
 		self.pos = new_pos;
 		retv
 	}
 }
 
+/// Writes a string the way the reader reads it back.
+fn write_string(s: &str, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "\"")?;
+	for c in s.chars() {
+		match c {
+			'"' => write!(f, "\\\"")?,
+			'\\' => write!(f, "\\\\")?,
+			'\n' => write!(f, "\\n")?,
+			'\t' => write!(f, "\\t")?,
+			c => write!(f, "{}", c)?,
+		}
+	}
+	write!(f, "\"")
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match *self {
@@ -84,31 +350,21 @@ impl fmt::Display for Value {
 			Value::True => write!(f, "true"),
 			Value::Int(ref i) => write!(f, "{}", i),
 			Value::Ident(ref s) => write!(f, "{}", s),
-			Value::Cons(ref inner) => {
-				write!(f, "(")?;
-				print_list_inner(&*inner, f)?;
-				write!(f, ")")
-			},
-			Value::Fn(ref fun, ev) => write!(f, "(fn {})", fun),
+			Value::Str(ref s) => write_string(s, f),
+			Value::Cons(_) => write_shared(self, f),
+			Value::Fn(Func::HFunc(b), false) => write!(f, "#<special {}>", b.name),
+			Value::Fn(ref fun, _) => write!(f, "{}", fun),
+			Value::Record(_) => write_shared(self, f),
+			Value::Class(ref c) => write!(f, "{}", c),
+			Value::Object(_) => write_shared(self, f),
+			Value::Promise(ref p) => write!(f, "{}", &*p.borrow()),
+			Value::Coroutine(ref c) => write!(f, "{}", &**c),
+			Value::Module(ref m) => write!(f, "{}", &**m),
 			Value::EOF => write!(f, "EOF"),
 		}
     }
 }
 
-fn print_list_inner(inner: &(LCell<Value>, LCell<Value>), f: &mut fmt::Formatter) -> fmt::Result {
-	let (ref h, ref t) = *inner;
-	write!(f, "{}", &*h.borrow())?;
-	match *t.borrow() {
-		Value::Nil => {},
-		Value::Cons(ref i) => {
-			write!(f, " ")?;
-			print_list_inner(i, f)?
-		},
-		_ => panic!("tis not a list!"),
-	}
-	write!(f, "")
-}
-
 impl FromIterator<LCell<Value>> for Value {
 	fn from_iter<I: IntoIterator<Item=LCell<Value>>>(iter: I) -> Self {
         let mut builder = ListBuilder::new();
@@ -122,6 +378,7 @@ impl FromIterator<LCell<Value>> for Value {
 pub struct ListBuilder {
 	head: Option<Value>,
 	cur: Value,
+	holder: Option<LCell<Value>>,
 }
 
 impl ListBuilder {
@@ -129,9 +386,24 @@ impl ListBuilder {
 		ListBuilder {
 			head: None,
 			cur: Value::Nil,
+			holder: None,
 		}
 	}
 
+	/// Builds an improper list whose last pair has `tail` as its tail cell,
+	/// instead of a fresh cell holding `()`.
+	pub fn build_with_tail(mut self, tail: LCell<Value>) -> Value {
+		let last = match self.cur {
+			Value::Cons((ref h, _)) => Value::Cons((h.clone(), tail)),
+			_ => return tail.borrow().clone(),
+		};
+		match self.holder {
+			None => self.head = Some(last),
+			Some(ref cell) => *cell.borrow_mut() = last,
+		}
+		self.build()
+	}
+
 	pub fn build(self) -> Value {
 		match self.head {
 			None => Value::Nil,
@@ -139,7 +411,13 @@ impl ListBuilder {
 		}
 	}
 
+	/// Appends a copy of `v` in a cell of its own, see `cons`.
 	pub fn push(&mut self, v: LCell<Value>) {
+		self.push_cell(lcell(v.borrow().clone()));
+	}
+
+	/// Appends `v` itself, for the reader, which fills some cells in later.
+	pub fn push_cell(&mut self, v: LCell<Value>) {
 		let next = (v, lcell(Value::Nil));
 		if self.head.is_none() {
 			self.head = Some(Value::Cons(next.clone()));
@@ -148,6 +426,7 @@ impl ListBuilder {
 			let new;
 			if let Value::Cons(ref c) = self.cur {
 				*c.1.borrow_mut() = Value::Cons(next.clone());
+				self.holder = Some(c.1.clone());
 				new = Value::Cons(next);
 			} else {
 				unreachable!();
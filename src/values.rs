@@ -0,0 +1,120 @@
+use super::*;
+
+thread_local! {
+	/// The values of the last `values` call that did not return exactly one
+	/// value, along with the cell it returned in their place.
+	static RESULTS: RefCell<Option<(LCell<Value>, Vec<LCell<Value>>)>> = RefCell::new(None);
+}
+
+/// `(values x...)` returns its first argument, or nil when there is none,
+/// so every context that expects a single value gets one. The rest are
+/// kept aside for `call-with-values`, `receive` and `let-values`, which
+/// recognize the returned cell and pick them up.
+pub fn fn_values(params: LCell<Value>, _env: LCell<Bindings>) -> LCell<Value> {
+	let vals: Vec<LCell<Value>> = params.borrow().iter().collect();
+	if vals.len() == 1 {
+		forget_values();
+		return vals[0].clone();
+	}
+	let first = match vals.first() {
+		Some(v) => lcell(v.borrow().clone()),
+		None => nil(),
+	};
+	RESULTS.with(|r| *r.borrow_mut() = Some((first.clone(), vals)));
+	first
+}
+
+/// Drops the values kept aside, once the evaluation that could hand them
+/// on is over.
+pub fn forget_values() {
+	RESULTS.with(|r| *r.borrow_mut() = None);
+}
+
+/// Keeps the values kept aside only if `v` is the cell returned in their
+/// place, so a call returning the result of `values` hands them on.
+pub fn keep_values(v: &LCell<Value>) {
+	RESULTS.with(|r| {
+		let stale = r.borrow().as_ref().map_or(false, |&(ref first, _)| !same_cell(first, v));
+		if stale {
+			*r.borrow_mut() = None;
+		}
+	});
+}
+
+/// All the values `v` stands for: those of the `values` call that returned
+/// it, or just `v` itself.
+pub fn take_values(v: LCell<Value>) -> Vec<LCell<Value>> {
+	match RESULTS.with(|r| r.borrow_mut().take()) {
+		Some((ref first, ref vals)) if same_cell(first, &v) => vals.clone(),
+		_ => vec![v],
+	}
+}
+
+pub fn fn_call_with_values(params: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = params.borrow().iter();
+	let producer = it.next().unwrap();
+	let consumer = it.next().unwrap();
+	let vals = take_values(apply(producer, nil(), env.clone()));
+	apply(consumer, lcell(vals.into_iter().collect()), env)
+}
+
+/// Binds `formals` to `vals` in `scope`. Formals are a list of names, which
+/// must match the number of values, a dotted list whose last name takes the
+/// rest of them as a list, or a single name taking all of them.
+fn bind_formals(formals: &LCell<Value>, vals: Vec<LCell<Value>>, scope: &mut Bindings, form: &str) {
+	let count = vals.len();
+	let mut vals = vals.into_iter();
+	let mut pos = formals.clone();
+	loop {
+		let next = match *pos.borrow() {
+			Value::Nil => {
+				if vals.next().is_some() {
+					panic!("{} got {} values, more than {} expects", form, count, &*formals.borrow());
+				}
+				return;
+			},
+			Value::Ident(_) => {
+				scope.set_binding(&*pos.borrow(), lcell(vals.collect()));
+				return;
+			},
+			Value::Cons((ref name, ref rest)) => {
+				if !name.borrow().has_type("symbol") {
+					panic!("{} formals must be names, found {}", form, &*name.borrow());
+				}
+				let v = vals.next().unwrap_or_else(|| panic!("{} got {} values, fewer than {} expects", form, count, &*formals.borrow()));
+				scope.set_binding(&*name.borrow(), v);
+				rest.clone()
+			},
+			ref v => panic!("{} formals must be names, found {}", form, v),
+		};
+		pos = next;
+	}
+}
+
+/// `(receive formals expr body...)` evaluates body with formals bound to
+/// the values of expr.
+pub fn eval_receive(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let formals = it.next().unwrap();
+	let expr = it.next().unwrap();
+	let vals = take_values(eval(expr, env.clone()));
+	let mut scope = make_empty_bindings(env);
+	bind_formals(&formals, vals, &mut scope, "receive");
+	eval_sequence(it, lcell(scope))
+}
+
+/// `(let-values ((formals expr)...) body...)` evaluates every expr in the
+/// enclosing environment, then body with all the formals bound.
+pub fn eval_let_values(arguments: LCell<Value>, env: LCell<Bindings>) -> LCell<Value> {
+	let mut it = arguments.borrow().iter();
+	let clauses = it.next().unwrap();
+	let mut scope = make_empty_bindings(env.clone());
+	for clause in clauses.borrow().iter() {
+		let mut parts = clause.borrow().iter();
+		let formals = parts.next().expect("let-values binding without formals");
+		let expr = parts.next().expect("let-values binding without an expression");
+		let vals = take_values(eval(expr, env.clone()));
+		bind_formals(&formals, vals, &mut scope, "let-values");
+	}
+	eval_sequence(it, lcell(scope))
+}